@@ -1,7 +1,11 @@
-use crate::cfg::{Bundle, Cfg, IgnoreProp, Instance};
+use crate::cfg::{Backups, BuildWait, Bundle, Cfg, IgnoreProp, Instance, Snapshots};
 use crate::cmd::Opt;
 use crate::path::Path;
+use anyhow::Result;
 use getset::{CopyGetters, Getters};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
 
 #[derive(Debug, Getters, CopyGetters, Default, Clone)]
 pub(crate) struct GetArgs {
@@ -16,16 +20,62 @@ pub(crate) struct GetArgs {
 
     #[getset(get = "pub")]
     ignore_properties: Vec<IgnoreProp>,
+
+    #[getset(get = "pub")]
+    build_wait: BuildWait,
+
+    #[getset(get_copy = "pub")]
+    dry_run: bool,
+
+    #[getset(get_copy = "pub")]
+    backups: Backups,
+    /// directory of the project, holding backups and snapshots
+    #[getset(get = "pub")]
+    root: PathBuf,
 }
 
 impl GetArgs {
-    pub(crate) fn new<S: Into<String>>(path: S, cfg: Cfg, opt: &Opt) -> Self {
-        Self {
+    pub(crate) fn new<S: Into<String>>(path: S, cfg: Cfg, opt: &Opt) -> Result<Self> {
+        Ok(Self {
             path: Path::new(path),
-            instance: cfg.instance(opt.profile.as_ref()),
+            instance: cfg.instance(opt.profile.as_ref())?,
             debug: opt.debug,
+            dry_run: opt.dry_run,
+            build_wait: cfg.build_wait(),
+            backups: cfg.backups(),
             ignore_properties: cfg.ignore_properties,
-        }
+            root: cfg.root.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Getters, CopyGetters, Default, Clone)]
+pub(crate) struct DiffArgs {
+    #[getset(get = "pub")]
+    path: Path,
+
+    #[getset(get = "pub")]
+    instance: Instance,
+
+    #[getset(get_copy = "pub")]
+    debug: bool,
+
+    #[getset(get = "pub")]
+    ignore_properties: Vec<IgnoreProp>,
+
+    #[getset(get = "pub")]
+    build_wait: BuildWait,
+}
+
+impl DiffArgs {
+    pub(crate) fn new<S: Into<String>>(path: S, cfg: Cfg, opt: &Opt) -> Result<Self> {
+        Ok(Self {
+            path: Path::new(path),
+            instance: cfg.instance(opt.profile.as_ref())?,
+            debug: opt.debug,
+            build_wait: cfg.build_wait(),
+            ignore_properties: cfg.ignore_properties,
+        })
     }
 }
 
@@ -35,19 +85,80 @@ pub(crate) struct PutArgs {
     path: Path,
 
     #[getset(get = "pub")]
-    instance: Instance,
+    instances: Vec<Instance>,
 
     #[getset(get_copy = "pub")]
     debug: bool,
+
+    #[getset(get_copy = "pub")]
+    dry_run: bool,
+
+    #[getset(get_copy = "pub")]
+    snapshot: bool,
+
+    #[getset(get = "pub")]
+    build_wait: BuildWait,
+
+    #[getset(get_copy = "pub")]
+    snapshots: Snapshots,
+    /// directory of the project, holding backups and snapshots
+    #[getset(get = "pub")]
+    root: PathBuf,
 }
 
 impl PutArgs {
-    pub(crate) fn new<S: Into<String>>(path: S, cfg: &Cfg, opt: &Opt) -> Self {
-        Self {
+    pub(crate) fn new<S: Into<String>>(
+        path: S,
+        snapshot: bool,
+        cfg: &Cfg,
+        opt: &Opt,
+    ) -> Result<Self> {
+        Ok(Self {
             path: Path::new(path),
-            instance: cfg.instance(opt.profile.as_ref()),
+            instances: cfg.instances(opt.profile.as_ref())?,
             debug: opt.debug,
-        }
+            dry_run: opt.dry_run,
+            snapshot,
+            build_wait: cfg.build_wait(),
+            snapshots: cfg.snapshots(),
+            root: cfg.root.clone(),
+        })
+    }
+
+    /// Tells if a snapshot of the remote content is saved before installing to the `instance`,
+    /// either because of `--snapshot` or the profile setting.
+    pub(crate) fn snapshot_of(&self, instance: &Instance) -> bool {
+        self.snapshot || *instance.snapshot_before_put()
+    }
+}
+
+#[derive(Debug, Getters, CopyGetters, Default, Clone)]
+pub(crate) struct RollbackArgs {
+    #[getset(get = "pub")]
+    path: Path,
+
+    #[getset(get = "pub")]
+    instance: Instance,
+
+    #[getset(get_copy = "pub")]
+    debug: bool,
+
+    #[getset(get_copy = "pub")]
+    dry_run: bool,
+    /// directory of the project, holding backups and snapshots
+    #[getset(get = "pub")]
+    root: PathBuf,
+}
+
+impl RollbackArgs {
+    pub(crate) fn new<S: Into<String>>(path: S, cfg: &Cfg, opt: &Opt) -> Result<Self> {
+        Ok(Self {
+            path: Path::new(path),
+            instance: cfg.instance(opt.profile.as_ref())?,
+            debug: opt.debug,
+            dry_run: opt.dry_run,
+            root: cfg.root.clone(),
+        })
     }
 }
 
@@ -64,26 +175,249 @@ pub(crate) struct GetBundleArgs {
 
     #[getset(get = "pub")]
     ignore_properties: Vec<IgnoreProp>,
+
+    #[getset(get = "pub")]
+    build_wait: BuildWait,
+
+    #[getset(get_copy = "pub")]
+    backups: Backups,
+    /// directory of the project, holding backups and snapshots
+    #[getset(get = "pub")]
+    root: PathBuf,
 }
 
 impl GetBundleArgs {
-    pub(crate) fn new<S: Into<String>>(name: S, cfg: Cfg, opt: &Opt) -> Self {
-        Self {
-            bundle: cfg.bundle(Some(&name.into())),
-            instance: cfg.instance(opt.profile.as_ref()),
+    pub(crate) fn new<S: Into<String>>(name: S, cfg: Cfg, opt: &Opt) -> Result<Self> {
+        Ok(Self {
+            bundle: cfg.bundle(&name.into())?,
+            instance: cfg.instance(opt.profile.as_ref())?,
             debug: opt.debug,
+            build_wait: cfg.build_wait(),
+            backups: cfg.backups(),
             ignore_properties: cfg.ignore_properties,
+            root: cfg.root.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Getters, CopyGetters, Default, Clone)]
+pub(crate) struct PutBundleArgs {
+    #[getset(get = "pub")]
+    bundle: Bundle,
+
+    #[getset(get = "pub")]
+    instance: Instance,
+
+    #[getset(get_copy = "pub")]
+    debug: bool,
+
+    #[getset(get_copy = "pub")]
+    dry_run: bool,
+}
+
+impl PutBundleArgs {
+    pub(crate) fn new<S: Into<String>>(name: S, cfg: &Cfg, opt: &Opt) -> Result<Self> {
+        Ok(Self {
+            bundle: cfg.bundle(&name.into())?,
+            instance: cfg.instance(opt.profile.as_ref())?,
+            debug: opt.debug,
+            dry_run: opt.dry_run,
+        })
+    }
+}
+
+#[derive(Debug, Getters, CopyGetters, Default, Clone)]
+pub(crate) struct WatchArgs {
+    #[getset(get = "pub")]
+    path: Path,
+
+    #[getset(get = "pub")]
+    instance: Instance,
+
+    #[getset(get_copy = "pub")]
+    debug: bool,
+
+    #[getset(get_copy = "pub")]
+    debounce: Duration,
+}
+
+impl WatchArgs {
+    pub(crate) fn new<S: Into<String>>(
+        path: S,
+        debounce_ms: u64,
+        cfg: &Cfg,
+        opt: &Opt,
+    ) -> Result<Self> {
+        Ok(Self {
+            path: Path::new(path),
+            instance: cfg.instance(opt.profile.as_ref())?,
+            debug: opt.debug,
+            debounce: Duration::from_millis(debounce_ms),
+        })
+    }
+}
+
+#[derive(Debug, Getters, CopyGetters, Default, Clone)]
+pub(crate) struct PkgArgs {
+    #[getset(get = "pub")]
+    instance: Instance,
+
+    #[getset(get_copy = "pub")]
+    dry_run: bool,
+}
+
+impl PkgArgs {
+    pub(crate) fn new(cfg: &Cfg, opt: &Opt) -> Result<Self> {
+        Ok(Self {
+            instance: cfg.instance(opt.profile.as_ref())?,
+            dry_run: opt.dry_run,
+        })
+    }
+}
+
+#[derive(Debug, Getters, CopyGetters, Default, Clone)]
+pub(crate) struct LsArgs {
+    #[getset(get = "pub")]
+    path: Path,
+
+    #[getset(get = "pub")]
+    instance: Instance,
+
+    #[getset(get_copy = "pub")]
+    depth: u32,
+}
+
+impl LsArgs {
+    pub(crate) fn new<S: Into<String>>(path: S, depth: u32, cfg: &Cfg, opt: &Opt) -> Result<Self> {
+        Ok(Self {
+            path: Path::new(path),
+            instance: cfg.instance(opt.profile.as_ref())?,
+            depth,
+        })
+    }
+}
+
+#[derive(Debug, Getters, CopyGetters, Default, Clone)]
+pub(crate) struct CatArgs {
+    #[getset(get = "pub")]
+    path: Path,
+
+    #[getset(get = "pub")]
+    instance: Instance,
+
+    #[getset(get_copy = "pub")]
+    debug: bool,
+
+    #[getset(get_copy = "pub")]
+    format: CatFormat,
+
+    #[getset(get = "pub")]
+    ignore_properties: Vec<IgnoreProp>,
+
+    #[getset(get = "pub")]
+    build_wait: BuildWait,
+}
+
+impl CatArgs {
+    pub(crate) fn new<S: Into<String>>(
+        path: S,
+        format: CatFormat,
+        cfg: Cfg,
+        opt: &Opt,
+    ) -> Result<Self> {
+        Ok(Self {
+            path: Path::new(path),
+            instance: cfg.instance(opt.profile.as_ref())?,
+            debug: opt.debug,
+            format,
+            build_wait: cfg.build_wait(),
+            ignore_properties: cfg.ignore_properties,
+        })
+    }
+}
+
+#[derive(Debug, Getters, CopyGetters, Default, Clone)]
+pub(crate) struct RmArgs {
+    #[getset(get = "pub")]
+    path: Path,
+
+    #[getset(get = "pub")]
+    instance: Instance,
+
+    #[getset(get_copy = "pub")]
+    debug: bool,
+
+    #[getset(get_copy = "pub")]
+    yes: bool,
+
+    #[getset(get_copy = "pub")]
+    dry_run: bool,
+}
+
+impl RmArgs {
+    pub(crate) fn new<S: Into<String>>(path: S, yes: bool, cfg: &Cfg, opt: &Opt) -> Result<Self> {
+        Ok(Self {
+            path: Path::new(path),
+            instance: cfg.instance(opt.profile.as_ref())?,
+            debug: opt.debug,
+            yes,
+            dry_run: opt.dry_run,
+        })
+    }
+}
+
+/// Output format of `je cat`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub(crate) enum CatFormat {
+    /// properties returned by Sling GET servlet
+    #[default]
+    Json,
+    /// `.content.xml` downloaded in a package
+    Xml,
+}
+
+impl FromStr for CatFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "json" => Ok(CatFormat::Json),
+            "xml" => Ok(CatFormat::Xml),
+            other => Err(format!("unknown format '{}', use json or xml", other)),
         }
     }
 }
 
+/// Age of packages, e.g. `30m`, `12h` or `7d`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) struct Age(pub(crate) Duration);
+
+impl FromStr for Age {
+    type Err = String;
+
+    fn from_str(age: &str) -> Result<Self, Self::Err> {
+        let err = || format!("incorrect age '{}', use e.g. 30m, 12h or 7d", age);
+        let (split, unit) = age.char_indices().last().ok_or_else(err)?;
+        let value: u64 = age[..split].parse().map_err(|_| err())?;
+        let multiplier = match unit {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 60 * 60 * 24,
+            _ => return Err(err()),
+        };
+        let secs = value.checked_mul(multiplier).ok_or_else(err)?;
+        Ok(Age(Duration::from_secs(secs)))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::cfg::IgnoreType;
 
     #[test]
-    fn test_get_args_creation() {
+    fn test_get_args_creation() -> Result<()> {
         // given
         let path = "/some/path";
         let cfg = Cfg {
@@ -95,11 +429,14 @@ mod tests {
                 Instance::new("author-1", "http://localhost:4502", "admin", "admin"),
                 Instance::new("publish-1", "http://localhost:4503", "admin", "admin"),
             ],
+            build_wait: Some(BuildWait::new(10, 50, 500)),
+            backups: Some(Backups::new(3)),
             ..Cfg::default()
         };
         let opt = Opt {
             debug: false,
             profile: Some("author-1".into()),
+            dry_run: true,
             ..Opt::default()
         };
         let expected = GetArgs {
@@ -110,20 +447,71 @@ mod tests {
                 ignore_type: crate::cfg::IgnoreType::Contains,
                 value: "some value".into(),
             }],
+            build_wait: BuildWait::new(10, 50, 500),
+            dry_run: true,
+            backups: Backups::new(3),
+            root: PathBuf::new(),
         };
 
         // when
-        let get_args = GetArgs::new(path, cfg, &opt);
+        let get_args = GetArgs::new(path, cfg, &opt)?;
 
         // then
         assert_eq!(expected.path.full(), get_args.path.full());
         assert_eq!(expected.instance, get_args.instance);
         assert_eq!(expected.debug, get_args.debug);
         assert_eq!(expected.ignore_properties, get_args.ignore_properties);
+        assert_eq!(expected.build_wait, get_args.build_wait);
+        assert_eq!(expected.dry_run, get_args.dry_run);
+        assert_eq!(expected.backups, get_args.backups);
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_args_creation() -> Result<()> {
+        // given
+        let path = "/some/path";
+        let cfg = Cfg {
+            ignore_properties: vec![IgnoreProp {
+                ignore_type: IgnoreType::Regex,
+                value: "jcr:last.*".into(),
+            }],
+            profiles: vec![
+                Instance::new("author", "http://localhost:4502", "admin", "admin"),
+                Instance::new("publish", "http://localhost:4503", "admin", "admin"),
+            ],
+            ..Cfg::default()
+        };
+        let opt = Opt {
+            debug: true,
+            profile: Some("publish".into()),
+            ..Opt::default()
+        };
+        let expected = DiffArgs {
+            path: Path::new("/some/path"),
+            instance: Instance::new("publish", "http://localhost:4503", "admin", "admin"),
+            debug: true,
+            ignore_properties: vec![IgnoreProp {
+                ignore_type: IgnoreType::Regex,
+                value: "jcr:last.*".into(),
+            }],
+            build_wait: BuildWait::default(),
+        };
+
+        // when
+        let actual = DiffArgs::new(path, cfg, &opt)?;
+
+        // then
+        assert_eq!(expected.path.full(), actual.path.full());
+        assert_eq!(expected.instance, actual.instance);
+        assert_eq!(expected.debug, actual.debug);
+        assert_eq!(expected.ignore_properties, actual.ignore_properties);
+        assert_eq!(expected.build_wait, actual.build_wait);
+        Ok(())
     }
 
     #[test]
-    fn test_put_args_creation() {
+    fn test_put_args_creation() -> Result<()> {
         // given
         let path = "/some/path";
         let cfg = Cfg {
@@ -131,6 +519,8 @@ mod tests {
                 Instance::new("int-author", "http://localhost:4502", "admin", "admin"),
                 Instance::new("int-publish", "http://localhost:4503", "admin", "admin"),
             ],
+            backups: Some(Backups::new(3)),
+            snapshots: Some(Snapshots::new(2)),
             ..Cfg::default()
         };
         let opt = Opt {
@@ -140,21 +530,64 @@ mod tests {
         };
         let expected = PutArgs {
             path: Path::new("/some/path"),
-            instance: Instance::new("int-publish", "http://localhost:4503", "admin", "admin"),
+            instances: vec![Instance::new(
+                "int-publish",
+                "http://localhost:4503",
+                "admin",
+                "admin",
+            )],
             debug: true,
+            dry_run: false,
+            snapshot: false,
+            build_wait: BuildWait::default(),
+            snapshots: Snapshots::new(2),
+            root: PathBuf::new(),
         };
 
         // when
-        let actual = PutArgs::new(path, &cfg, &opt);
+        let actual = PutArgs::new(path, false, &cfg, &opt)?;
 
         // then
         assert_eq!(expected.path.full(), actual.path.full());
-        assert_eq!(expected.instance, actual.instance);
+        assert_eq!(expected.instances, actual.instances);
         assert_eq!(expected.debug, actual.debug);
+        assert_eq!(expected.snapshot, actual.snapshot);
+        assert_eq!(expected.snapshots, actual.snapshots);
+        Ok(())
     }
 
     #[test]
-    fn test_get_bundle_args_creation() {
+    fn test_put_args_with_many_profiles() -> Result<()> {
+        // given
+        let mut author = Instance::new("author", "http://localhost:4502", "admin", "admin");
+        author.set_snapshot_before_put(true);
+        let publish = Instance::new("publish", "http://localhost:4503", "admin", "admin");
+        let cfg = Cfg {
+            profiles: vec![author.clone(), publish.clone()],
+            ..Cfg::default()
+        };
+        let opt = Opt {
+            profile: Some("author,publish".into()),
+            ..Opt::default()
+        };
+
+        // when
+        let without_flag = PutArgs::new("/some/path", false, &cfg, &opt)?;
+        let with_flag = PutArgs::new("/some/path", true, &cfg, &opt)?;
+
+        // then
+        assert_eq!(
+            without_flag.instances,
+            vec![author.clone(), publish.clone()]
+        );
+        assert!(without_flag.snapshot_of(&author));
+        assert!(!without_flag.snapshot_of(&publish));
+        assert!(with_flag.snapshot_of(&publish));
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_bundle_args_creation() -> Result<()> {
         // given
         let bundle_name = "test-bundle";
         let cfg = Cfg {
@@ -185,15 +618,169 @@ mod tests {
                 ignore_type: crate::cfg::IgnoreType::Contains,
                 value: "other value".into(),
             }],
+            build_wait: BuildWait::default(),
+            backups: Backups::default(),
+            root: PathBuf::new(),
         };
 
         // when
-        let actual = GetBundleArgs::new(bundle_name, cfg, &opt);
+        let actual = GetBundleArgs::new(bundle_name, cfg, &opt)?;
 
         // then
         assert_eq!(expected.bundle, actual.bundle);
         assert_eq!(expected.instance, actual.instance);
         assert_eq!(expected.debug, actual.debug);
         assert_eq!(expected.ignore_properties, actual.ignore_properties);
+        assert_eq!(expected.build_wait, actual.build_wait);
+        assert_eq!(expected.backups, actual.backups);
+        Ok(())
+    }
+
+    #[test]
+    fn test_put_bundle_args_creation() -> Result<()> {
+        // given
+        let bundle_name = "other";
+        let cfg = Cfg {
+            bundles: Some(vec![
+                Bundle::new("test-bundle", vec!["/some/file"]),
+                Bundle::new("other", vec!["/different/file", "/another/file"]),
+            ]),
+            profiles: vec![
+                Instance::new("prod-author", "http://localhost:4502", "admin", "admin"),
+                Instance::new("prod-publish", "http://localhost:4503", "admin", "admin"),
+            ],
+            ..Cfg::default()
+        };
+        let opt = Opt {
+            debug: true,
+            profile: Some("prod-publish".into()),
+            ..Opt::default()
+        };
+        let expected = PutBundleArgs {
+            bundle: Bundle::new("other", vec!["/different/file", "/another/file"]),
+            instance: Instance::new("prod-publish", "http://localhost:4503", "admin", "admin"),
+            debug: true,
+            dry_run: false,
+        };
+
+        // when
+        let actual = PutBundleArgs::new(bundle_name, &cfg, &opt)?;
+
+        // then
+        assert_eq!(expected.bundle, actual.bundle);
+        assert_eq!(expected.instance, actual.instance);
+        assert_eq!(expected.debug, actual.debug);
+        Ok(())
+    }
+
+    #[test]
+    fn test_watch_args_creation() -> Result<()> {
+        // given
+        let path = "/project/jcr_root/apps";
+        let cfg = Cfg {
+            profiles: vec![
+                Instance::new("author", "http://localhost:4502", "admin", "admin"),
+                Instance::new("publish", "http://localhost:4503", "admin", "admin"),
+            ],
+            ..Cfg::default()
+        };
+        let opt = Opt {
+            debug: false,
+            profile: Some("author".into()),
+            ..Opt::default()
+        };
+        let expected = WatchArgs {
+            path: Path::new("/project/jcr_root/apps"),
+            instance: Instance::new("author", "http://localhost:4502", "admin", "admin"),
+            debug: false,
+            debounce: Duration::from_millis(300),
+        };
+
+        // when
+        let actual = WatchArgs::new(path, 300, &cfg, &opt)?;
+
+        // then
+        assert_eq!(expected.path.full(), actual.path.full());
+        assert_eq!(expected.instance, actual.instance);
+        assert_eq!(expected.debug, actual.debug);
+        assert_eq!(expected.debounce, actual.debounce);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ls_args_creation() -> Result<()> {
+        // given
+        let cfg = Cfg {
+            profiles: vec![
+                Instance::new("author", "http://localhost:4502", "admin", "admin"),
+                Instance::new("publish", "http://localhost:4503", "admin", "admin"),
+            ],
+            ..Cfg::default()
+        };
+        let opt = Opt {
+            profile: Some("publish".into()),
+            ..Opt::default()
+        };
+
+        // when
+        let actual = LsArgs::new("/content/site", 2, &cfg, &opt)?;
+
+        // then
+        assert_eq!(actual.path.full(), "/content/site");
+        assert_eq!(
+            actual.instance,
+            Instance::new("publish", "http://localhost:4503", "admin", "admin")
+        );
+        assert_eq!(actual.depth, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cat_format_from_str() {
+        assert_eq!("json".parse(), Ok(CatFormat::Json));
+        assert_eq!("xml".parse(), Ok(CatFormat::Xml));
+        assert_eq!(
+            "yaml".parse::<CatFormat>(),
+            Err("unknown format 'yaml', use json or xml".to_string())
+        );
+    }
+
+    #[test]
+    fn test_age_parsing() {
+        // given
+        let ages = [
+            "45s",
+            "30m",
+            "12h",
+            "7d",
+            "",
+            "d",
+            "7",
+            "7w",
+            "-1d",
+            "7µ",
+            "18446744073709551615d",
+        ];
+
+        // when
+        let parsed: Vec<_> = ages.iter().map(|age| age.parse::<Age>().ok()).collect();
+
+        // then
+        assert_eq!(
+            parsed,
+            vec![
+                Some(Age(Duration::from_secs(45))),
+                Some(Age(Duration::from_secs(30 * 60))),
+                Some(Age(Duration::from_secs(12 * 60 * 60))),
+                Some(Age(Duration::from_secs(7 * 24 * 60 * 60))),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            ]
+        );
     }
 }
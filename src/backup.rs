@@ -0,0 +1,238 @@
+//! Snapshots of local files taken before they are overwritten with content of the instance,
+//! restored by `je undo`.
+use anyhow::{Context, Result};
+use log::{debug, info};
+use serde_derive::{Deserialize, Serialize};
+use std::fs;
+use std::path::{self, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use walkdir::WalkDir;
+
+/// Directory in the project root with local files saved before `get` overwrites them, one
+/// timestamped subdirectory per `get`. It's not `.je/backups`, as `.je` is the config file.
+pub(crate) const BACKUPS_DIR: &str = ".je-backups";
+
+const MANIFEST_FILE: &str = "snapshot.json";
+
+/// Describes which local paths were saved in the snapshot.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    /// absolute local path
+    path: PathBuf,
+
+    /// name of the copy inside of the snapshot dir; `None` when the path didn't exist,
+    /// so restoring means removing it
+    copy: Option<String>,
+}
+
+/// Saves current state of `paths` as a new snapshot in `backups_dir` and removes the oldest
+/// snapshots, so at most `keep` of them are left.
+pub(crate) fn snapshot<P: AsRef<Path>>(
+    backups_dir: &Path,
+    paths: &[P],
+    keep: usize,
+) -> Result<Option<PathBuf>> {
+    if keep == 0 {
+        debug!("snapshots are turned off");
+        return Ok(None);
+    }
+    let snapshot_dir = backups_dir.join(snapshot_name()?);
+    info!("saving local files to {}", snapshot_dir.display());
+    fs::create_dir_all(&snapshot_dir)?;
+    let mut manifest = Manifest::default();
+    for (idx, path) in paths.iter().enumerate() {
+        let path = path::absolute(path)?;
+        let copy = if path.exists() {
+            let copy = idx.to_string();
+            copy_all(&path, &snapshot_dir.join(&copy))
+                .context(format!("failed to back up {}", path.display()))?;
+            Some(copy)
+        } else {
+            None
+        };
+        manifest.entries.push(Entry { path, copy });
+    }
+    fs::write(
+        snapshot_dir.join(MANIFEST_FILE),
+        serde_json::to_string_pretty(&manifest)?,
+    )?;
+    prune(backups_dir, keep)?;
+    Ok(Some(snapshot_dir))
+}
+
+/// Restores the most recent snapshot and removes it, so the next call restores the one before.
+/// Returns restored paths, or `None` when there is no snapshot.
+pub(crate) fn restore_latest(backups_dir: &Path) -> Result<Option<Vec<PathBuf>>> {
+    let snapshot_dir = match snapshots(backups_dir)?.pop() {
+        Some(snapshot_dir) => snapshot_dir,
+        None => return Ok(None),
+    };
+    info!("restoring local files from {}", snapshot_dir.display());
+    let manifest: Manifest = serde_json::from_str(
+        &fs::read_to_string(snapshot_dir.join(MANIFEST_FILE))
+            .context(format!("broken snapshot {}", snapshot_dir.display()))?,
+    )?;
+    let mut restored = Vec::new();
+    for entry in manifest.entries {
+        remove(&entry.path)?;
+        if let Some(copy) = &entry.copy {
+            copy_all(&snapshot_dir.join(copy), &entry.path)
+                .context(format!("failed to restore {}", entry.path.display()))?;
+        }
+        restored.push(entry.path);
+    }
+    fs::remove_dir_all(&snapshot_dir)?;
+    Ok(Some(restored))
+}
+
+/// Name of a new snapshot directory.
+pub(crate) fn snapshot_name() -> Result<String> {
+    // zero-padded, so names sort the same way as the times they were taken at
+    Ok(format!(
+        "{:020}",
+        SystemTime::now().duration_since(UNIX_EPOCH)?.as_micros()
+    ))
+}
+
+fn snapshots(backups_dir: &Path) -> Result<Vec<PathBuf>> {
+    snapshot_dirs(backups_dir, MANIFEST_FILE)
+}
+
+/// Subdirectories of `dir` containing the `manifest` file, from the oldest to the most recent one.
+pub(crate) fn snapshot_dirs(dir: &Path, manifest: &str) -> Result<Vec<PathBuf>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut snapshots: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .map(|e| e.path())
+        .filter(|p| p.join(manifest).is_file())
+        .collect();
+    snapshots.sort();
+    Ok(snapshots)
+}
+
+fn prune(backups_dir: &Path, keep: usize) -> Result<()> {
+    let snapshots = snapshots(backups_dir)?;
+    let excess = snapshots.len().saturating_sub(keep);
+    for snapshot_dir in &snapshots[..excess] {
+        debug!("removing old snapshot {}", snapshot_dir.display());
+        fs::remove_dir_all(snapshot_dir)?;
+    }
+    Ok(())
+}
+
+fn remove(path: &Path) -> Result<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else if path.exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+fn copy_all(from: &Path, to: &Path) -> Result<()> {
+    for entry in WalkDir::new(from) {
+        let entry = entry?;
+        let target = match entry.path().strip_prefix(from)? {
+            rel if rel.as_os_str().is_empty() => to.to_path_buf(),
+            rel => to.join(rel),
+        };
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&target)?;
+        } else {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::{create_dir_all, read_to_string, write};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_snapshot_and_restore() -> Result<()> {
+        // given
+        let tmp_dir = TempDir::new()?;
+        let backups_dir = tmp_dir.path().join(BACKUPS_DIR);
+        let dir = tmp_dir.path().join("jcr_root/apps/x");
+        let file = tmp_dir.path().join("jcr_root/conf/config.xml");
+        let missing = tmp_dir.path().join("jcr_root/content/new");
+        create_dir_all(dir.join("nested"))?;
+        write(dir.join("nested/.content.xml"), "local edit")?;
+        create_dir_all(file.parent().unwrap())?;
+        write(&file, "local config")?;
+        snapshot(&backups_dir, &[&dir, &file, &missing], 10)?;
+        write(dir.join("nested/.content.xml"), "remote")?;
+        write(dir.join("added.txt"), "remote")?;
+        write(&file, "remote config")?;
+        create_dir_all(&missing)?;
+
+        // when
+        let restored = restore_latest(&backups_dir)?;
+
+        // then
+        assert_eq!(
+            restored,
+            Some(vec![dir.clone(), file.clone(), missing.clone()])
+        );
+        assert_eq!(
+            read_to_string(dir.join("nested/.content.xml"))?,
+            "local edit"
+        );
+        assert!(!dir.join("added.txt").exists());
+        assert_eq!(read_to_string(&file)?, "local config");
+        assert!(!missing.exists());
+        assert_eq!(restore_latest(&backups_dir)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_keeps_most_recent() -> Result<()> {
+        // given
+        let tmp_dir = TempDir::new()?;
+        let backups_dir = tmp_dir.path().join(BACKUPS_DIR);
+        let file = tmp_dir.path().join("file.txt");
+
+        // when
+        for content in ["first", "second", "third"] {
+            write(&file, content)?;
+            snapshot(&backups_dir, &[&file], 2)?;
+        }
+
+        // then
+        assert_eq!(snapshots(&backups_dir)?.len(), 2);
+        restore_latest(&backups_dir)?;
+        assert_eq!(read_to_string(&file)?, "third");
+        restore_latest(&backups_dir)?;
+        assert_eq!(read_to_string(&file)?, "second");
+        assert_eq!(restore_latest(&backups_dir)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_turned_off() -> Result<()> {
+        // given
+        let tmp_dir = TempDir::new()?;
+        let backups_dir = tmp_dir.path().join(BACKUPS_DIR);
+
+        // when
+        let snapshot_dir = snapshot(&backups_dir, &[tmp_dir.path()], 0)?;
+
+        // then
+        assert_eq!(snapshot_dir, None);
+        assert!(!backups_dir.exists());
+        Ok(())
+    }
+}
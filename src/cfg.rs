@@ -1,47 +1,305 @@
 use crate::cfgmgr::CURRENT_VERSION;
-use getset::Getters;
+use crate::path::Path;
+use anyhow::{bail, Context, Result};
+use getset::{CopyGetters, Getters, Setters};
+use regex::Regex;
+use reqwest::Url;
+use serde::{Serialize as _, Serializer};
 use serde_derive::{Deserialize, Serialize};
 use std::convert::Into;
+use std::fmt;
+use std::path::{Path as OsPath, PathBuf};
+use std::str::FromStr;
+
+/// Prefix of `user` and `pass` values which are read from the environment variable.
+pub(crate) const ENV_PREFIX: &str = "env:";
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub(crate) struct Cfg {
     pub(crate) version: Option<String>,
 
+    #[serde(default)]
     pub(crate) ignore_properties: Vec<IgnoreProp>,
 
-    #[serde(rename = "profile")]
+    #[serde(rename = "profile", default)]
     pub(crate) profiles: Vec<Instance>,
 
     #[serde(rename = "bundle")]
     pub(crate) bundles: Option<Vec<Bundle>>,
+
+    #[serde(rename = "group")]
+    pub(crate) groups: Option<Vec<Group>>,
+
+    #[serde(rename = "build")]
+    pub(crate) build_wait: Option<BuildWait>,
+
+    pub(crate) backups: Option<Backups>,
+
+    pub(crate) snapshots: Option<Snapshots>,
+
+    /// directory of the project config, holding backups and snapshots; the current directory
+    /// when there is no project config
+    #[serde(skip)]
+    pub(crate) root: PathBuf,
 }
 
 impl Cfg {
-    pub(crate) fn instance(&self, profile: Option<&String>) -> Instance {
-        let default_instance = Instance::default();
-        let profiles = self.profiles.clone();
+    /// Profile selected with `--profile`; without the selection, the first profile is used
+    /// (or the default one, when there are no profiles).
+    pub(crate) fn instance(&self, profile: Option<&String>) -> Result<Instance> {
         match profile {
-            Some(name) => profiles
-                .into_iter()
-                .find(|p| p.name == *name)
-                .unwrap_or(default_instance),
-            None => profiles.into_iter().next().unwrap_or(default_instance),
+            Some(name) => match self.profiles.iter().find(|p| p.name == *name) {
+                Some(profile) => Ok(profile.clone()),
+                None => bail!("unknown profile '{}', {}", name, self.profile_choices()),
+            },
+            None => Ok(self.profiles.first().cloned().unwrap_or_default()),
+        }
+    }
+
+    /// Instances selected with `--profile`, which is a comma-separated list of profile and group
+    /// names; without the selection, the first profile is used.
+    pub(crate) fn instances(&self, selection: Option<&String>) -> Result<Vec<Instance>> {
+        let selection = match selection {
+            Some(selection) => selection,
+            None => return Ok(vec![self.instance(None)?]),
+        };
+        let mut instances: Vec<Instance> = Vec::new();
+        for name in selection
+            .split(',')
+            .map(str::trim)
+            .filter(|n| !n.is_empty())
+        {
+            let names = match self.group(name) {
+                Some(group) => group.profiles.clone(),
+                None => vec![name.to_string()],
+            };
+            for name in names {
+                match self.profiles.iter().find(|p| p.name == name) {
+                    Some(_) if instances.iter().any(|i| i.name == name) => {}
+                    Some(profile) => instances.push(profile.clone()),
+                    None => bail!(
+                        "unknown profile or group '{}', {}; {}",
+                        name,
+                        self.profile_choices(),
+                        choices("groups", self.groups.iter().flatten().map(|g| &g.name))
+                    ),
+                }
+            }
+        }
+        if instances.is_empty() {
+            bail!("no profile selected with '{}'", selection);
+        }
+        Ok(instances)
+    }
+
+    /// Tells if the `--profile` selection can refer to more than one instance.
+    pub(crate) fn selects_many(&self, selection: Option<&String>) -> bool {
+        selection.is_some_and(|s| s.contains(',') || self.group(s).is_some())
+    }
+
+    fn group(&self, name: &str) -> Option<&Group> {
+        self.groups.iter().flatten().find(|g| g.name == name)
+    }
+
+    pub(crate) fn bundle(&self, name: &str) -> Result<Bundle> {
+        match self.bundles.iter().flatten().find(|b| b.name == name) {
+            Some(bundle) => Ok(bundle.clone()),
+            None => bail!(
+                "unknown bundle '{}', {}",
+                name,
+                choices("bundles", self.bundles.iter().flatten().map(|b| &b.name))
+            ),
+        }
+    }
+
+    fn profile_choices(&self) -> String {
+        choices("profiles", self.profiles.iter().map(|p| &p.name))
+    }
+
+    /// Checks the whole config at once and reports all found problems together.
+    pub(crate) fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+        let bundles: Vec<&Bundle> = self.bundles.iter().flatten().collect();
+        let groups: Vec<&Group> = self.groups.iter().flatten().collect();
+        problems.extend(duplicates("profile", self.profiles.iter().map(|p| &p.name)));
+        problems.extend(duplicates("bundle", bundles.iter().map(|b| &b.name)));
+        problems.extend(duplicates("group", groups.iter().map(|g| &g.name)));
+        for profile in &self.profiles {
+            if let Err(e) = check_addr(&profile.addr) {
+                problems.push(format!(
+                    "profile '{}': incorrect addr '{}': {}",
+                    profile.name, profile.addr, e
+                ));
+            }
+        }
+        for prop in &self.ignore_properties {
+            if let (IgnoreType::Regex, Err(e)) = (&prop.ignore_type, Regex::new(&prop.value)) {
+                problems.push(format!(
+                    "ignore_properties: incorrect regex '{}': {}",
+                    prop.value,
+                    regex_error_reason(&e)
+                ));
+            }
+        }
+        for bundle in &bundles {
+            for path in &bundle.paths {
+                if let Err(e) = Path::new(path.path()).content() {
+                    problems.push(format!("bundle '{}': {}", bundle.name, e));
+                }
+                let filter = &path.filter;
+                for pattern in filter.include.iter().chain(&filter.exclude) {
+                    if let Err(e) = filter_regex(pattern) {
+                        problems.push(format!(
+                            "bundle '{}': incorrect filter pattern '{}': {}",
+                            bundle.name,
+                            pattern,
+                            regex_error_reason(&e)
+                        ));
+                    }
+                }
+            }
+        }
+        for group in &groups {
+            for name in &group.profiles {
+                if !self.profiles.iter().any(|p| p.name == *name) {
+                    problems.push(format!(
+                        "group '{}': unknown profile '{}', {}",
+                        group.name,
+                        name,
+                        self.profile_choices()
+                    ));
+                }
+            }
+        }
+        if !problems.is_empty() {
+            bail!("incorrect config:\n  - {}", problems.join("\n  - "));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn build_wait(&self) -> BuildWait {
+        self.build_wait.clone().unwrap_or_default()
+    }
+
+    pub(crate) fn backups(&self) -> Backups {
+        self.backups.unwrap_or_default()
+    }
+
+    pub(crate) fn snapshots(&self) -> Snapshots {
+        self.snapshots.unwrap_or_default()
+    }
+
+    /// Merges this (project) config over the `global` one: profiles, bundles and groups of both
+    /// are available, the project ones replace global ones with the same name and come first,
+    /// so the first project profile stays the default one; ignored properties of both are used
+    /// and the project sections replace global ones.
+    pub(crate) fn merged_over(self, global: Cfg) -> Cfg {
+        let mut ignore_properties = global.ignore_properties;
+        for prop in self.ignore_properties {
+            if !ignore_properties.contains(&prop) {
+                ignore_properties.push(prop);
+            }
+        }
+        Cfg {
+            version: self.version.or(global.version),
+            ignore_properties,
+            profiles: merge_named(self.profiles, global.profiles, |p| p.name.clone()),
+            bundles: merge_named_opt(self.bundles, global.bundles, |b| b.name.clone()),
+            groups: merge_named_opt(self.groups, global.groups, |g| g.name.clone()),
+            build_wait: self.build_wait.or(global.build_wait),
+            backups: self.backups.or(global.backups),
+            snapshots: self.snapshots.or(global.snapshots),
+            root: self.root,
+        }
+    }
+
+    /// Makes relative paths of the config (bundle paths, `pass_file`, `ca_cert` and token files)
+    /// relative to `dir`, the directory of the config file, instead of the current directory.
+    pub(crate) fn resolve_paths(&mut self, dir: &OsPath) {
+        let resolve = |path: &mut String| {
+            if OsPath::new(path.as_str()).is_relative() {
+                *path = dir.join(path.as_str()).display().to_string();
+            }
+        };
+        for profile in self.profiles.iter_mut() {
+            if let Some(pass_file) = profile.pass_file.as_mut() {
+                resolve(pass_file);
+            }
+            if let Some(ca_cert) = profile.http.ca_cert.as_mut() {
+                resolve(ca_cert);
+            }
+            if let Auth::TokenFile { path } = &mut profile.auth {
+                resolve(path);
+            }
+        }
+        for bundle in self.bundles.iter_mut().flatten() {
+            for bundle_path in bundle.paths.iter_mut() {
+                resolve(&mut bundle_path.path);
+            }
         }
     }
+}
 
-    pub(crate) fn bundle(&self, bundle: Option<&str>) -> Bundle {
-        if self.bundles.is_none() {
-            return Bundle::default();
+/// Lists names the user can choose from, e.g. `available profiles: author, publish`.
+pub(crate) fn choices<'a>(kind: &str, names_iter: impl Iterator<Item = &'a String>) -> String {
+    let mut names: Vec<&str> = Vec::new();
+    for name in names_iter {
+        if !names.contains(&name.as_str()) {
+            names.push(name);
         }
-        let bundles = self.bundles.clone().unwrap(); // can unwrap because it was checked earlier
-        match bundle {
-            Some(name) => bundles
-                .into_iter()
-                .find(|p| p.name == *name)
-                .unwrap_or_default(),
-            None => Bundle::default(),
+    }
+    if names.is_empty() {
+        format!("no {} are defined", kind)
+    } else {
+        format!("available {}: {}", kind, names.join(", "))
+    }
+}
+
+fn duplicates<'a>(kind: &str, names: impl Iterator<Item = &'a String>) -> Vec<String> {
+    let mut seen: Vec<&String> = Vec::new();
+    let mut problems = Vec::new();
+    for name in names {
+        if seen.contains(&name) {
+            problems.push(format!("duplicate {} '{}'", kind, name));
+        } else {
+            seen.push(name);
         }
     }
+    problems
+}
+
+fn check_addr(addr: &str) -> Result<()> {
+    let url = Url::parse(addr)?;
+    if !["http", "https"].contains(&url.scheme()) {
+        bail!("use http or https scheme");
+    }
+    if !url.has_host() {
+        bail!("missing host");
+    }
+    Ok(())
+}
+
+/// Project items followed by global ones which are not overridden by a project item of the same name.
+fn merge_named<T>(project: Vec<T>, global: Vec<T>, name: impl Fn(&T) -> String) -> Vec<T> {
+    let overridden: Vec<String> = project.iter().map(&name).collect();
+    let mut merged = project;
+    merged.extend(
+        global
+            .into_iter()
+            .filter(|g| !overridden.contains(&name(g))),
+    );
+    merged
+}
+
+fn merge_named_opt<T>(
+    project: Option<Vec<T>>,
+    global: Option<Vec<T>>,
+    name: impl Fn(&T) -> String,
+) -> Option<Vec<T>> {
+    match (project, global) {
+        (Some(project), Some(global)) => Some(merge_named(project, global, name)),
+        (project, global) => project.or(global),
+    }
 }
 
 impl Default for Cfg {
@@ -56,17 +314,39 @@ impl Default for Cfg {
             )],
             ignore_properties: vec![],
             bundles: None,
+            groups: None,
+            build_wait: None,
+            backups: None,
+            snapshots: None,
+            root: PathBuf::new(),
         }
     }
 }
 
-#[derive(Getters, Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
-#[getset(get = "pub")]
+/// Profile of the instance. `user` and `pass` can refer to an environment variable
+/// (`env:VAR_NAME`), the password can also be read from `pass_file` or, when both are missing,
+/// from the credentials store; they are resolved only for the selected profiles, see
+/// `cfgmgr::handle_cfg_load`.
+#[derive(Getters, Setters, Serialize, Deserialize, Eq, PartialEq, Clone)]
+#[getset(get = "pub", set = "pub(crate)")]
 pub(crate) struct Instance {
     name: String,
     addr: String,
+    #[serde(default)]
     user: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pass: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pass_file: Option<String>,
+    #[serde(default, skip_serializing_if = "Auth::is_basic")]
+    auth: Auth,
+    #[serde(default, skip_serializing_if = "HttpSettings::is_default")]
+    http: HttpSettings,
+    #[serde(default, skip_serializing_if = "PkgSettings::is_default")]
+    pkg: PkgSettings,
+    /// `put` saves a snapshot package of the remote content before installing, as with `--snapshot`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    snapshot_before_put: bool,
 }
 
 impl Instance {
@@ -76,6 +356,98 @@ impl Instance {
             addr: addr.into(),
             user: user.into(),
             pass: pass.into(),
+            pass_file: None,
+            auth: Auth::Basic,
+            http: HttpSettings::default(),
+            pkg: PkgSettings::default(),
+            snapshot_before_put: false,
+        }
+    }
+
+    /// Copy of the profile which can be shown, with the password and tokens hidden.
+    pub(crate) fn redacted(&self) -> Self {
+        Self {
+            pass: redacted(&self.pass).into(),
+            auth: self.auth.redacted(),
+            ..self.clone()
+        }
+    }
+}
+
+impl fmt::Debug for Instance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Instance")
+            .field("name", &self.name)
+            .field("addr", &self.addr)
+            .field("user", &self.user)
+            .field("pass", &redacted(&self.pass))
+            .field("pass_file", &self.pass_file)
+            .field("auth", &self.auth)
+            .field("http", &self.http)
+            .field("pkg", &self.pkg)
+            .field("snapshot_before_put", &self.snapshot_before_put)
+            .finish()
+    }
+}
+
+/// Secret which can be shown: references to environment variables are kept, other values
+/// are hidden.
+fn redacted(secret: &str) -> &str {
+    if secret.is_empty() || secret.starts_with(ENV_PREFIX) {
+        secret
+    } else {
+        "<redacted>"
+    }
+}
+
+/// How requests to the instance are authenticated; tokens can refer to an environment variable
+/// (`env:VAR_NAME`) the same as `user` and `pass`.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Clone, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum Auth {
+    /// `Authorization: Basic` header built from `user` and `pass`
+    #[default]
+    Basic,
+    /// `Authorization: Bearer` header with the token
+    Bearer { token: String },
+    /// `Authorization: Bearer` header with the token read from the file, either plain or JSON
+    /// with `accessToken` field (e.g. local development token from the developer console)
+    TokenFile { path: String },
+    /// `login-token` cookie, the same as the one set by AEM after logging in
+    LoginToken { token: String },
+}
+
+impl Auth {
+    pub(crate) fn is_basic(&self) -> bool {
+        *self == Auth::Basic
+    }
+
+    fn redacted(&self) -> Self {
+        match self {
+            Auth::Bearer { token } => Auth::Bearer {
+                token: redacted(token).into(),
+            },
+            Auth::LoginToken { token } => Auth::LoginToken {
+                token: redacted(token).into(),
+            },
+            other => other.clone(),
+        }
+    }
+}
+
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Auth::Basic => write!(f, "Basic"),
+            Auth::Bearer { token } => f
+                .debug_struct("Bearer")
+                .field("token", &redacted(token))
+                .finish(),
+            Auth::TokenFile { path } => f.debug_struct("TokenFile").field("path", path).finish(),
+            Auth::LoginToken { token } => f
+                .debug_struct("LoginToken")
+                .field("token", &redacted(token))
+                .finish(),
         }
     }
 }
@@ -87,6 +459,11 @@ impl Default for Instance {
             addr: "http://localhost:4502".into(),
             user: "admin".into(),
             pass: "admin".into(),
+            pass_file: None,
+            auth: Auth::Basic,
+            http: HttpSettings::default(),
+            pkg: PkgSettings::default(),
+            snapshot_before_put: false,
         }
     }
 }
@@ -95,19 +472,381 @@ impl Default for Instance {
 #[getset(get = "pub")]
 pub(crate) struct Bundle {
     name: String,
-    paths: Vec<String>,
+    #[serde(serialize_with = "serialize_bundle_paths")]
+    paths: Vec<BundlePath>,
+}
+
+/// TOML serializer can't write arrays mixing strings and tables, so when any of the paths
+/// has filter settings all of them are written as tables.
+fn serialize_bundle_paths<S: Serializer>(
+    paths: &[BundlePath],
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    if paths.iter().all(|p| p.filter == PathFilter::default()) {
+        return paths.serialize(serializer);
+    }
+    serializer.collect_seq(paths.iter().map(|p| RawBundlePath::Filtered {
+        path: p.path.clone(),
+        filter: p.filter.clone(),
+    }))
 }
 
 impl Bundle {
-    #[allow(dead_code)] // TODO: remove this
     pub(crate) fn new<S: Into<String>>(name: S, files: Vec<S>) -> Self {
         Self {
             name: name.into(),
-            paths: files.into_iter().map(Into::into).collect(),
+            paths: files.into_iter().map(BundlePath::new).collect(),
+        }
+    }
+}
+
+/// Named set of profiles, which can be selected with `--profile` at once.
+#[derive(Getters, Default, Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+#[getset(get = "pub")]
+pub(crate) struct Group {
+    name: String,
+    profiles: Vec<String>,
+}
+
+impl Group {
+    #[cfg(test)]
+    pub(crate) fn new<S: Into<String>>(name: S, profiles: Vec<S>) -> Self {
+        Self {
+            name: name.into(),
+            profiles: profiles.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// Path of the bundle; in the config it's either a plain string or a table with the path
+/// and its filter settings.
+#[derive(Getters, Default, Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+#[serde(from = "RawBundlePath", into = "RawBundlePath")]
+#[getset(get = "pub")]
+pub(crate) struct BundlePath {
+    path: String,
+    filter: PathFilter,
+}
+
+impl BundlePath {
+    pub(crate) fn new<S: Into<String>>(path: S) -> Self {
+        Self {
+            path: path.into(),
+            filter: PathFilter::default(),
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn with_filter<S: Into<String>>(path: S, filter: PathFilter) -> Self {
+        Self {
+            path: path.into(),
+            filter,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawBundlePath {
+    Plain(String),
+    Filtered {
+        path: String,
+        #[serde(flatten)]
+        filter: PathFilter,
+    },
+}
+
+impl From<RawBundlePath> for BundlePath {
+    fn from(raw: RawBundlePath) -> Self {
+        match raw {
+            RawBundlePath::Plain(path) => BundlePath::new(path),
+            RawBundlePath::Filtered { path, filter } => BundlePath { path, filter },
+        }
+    }
+}
+
+impl From<BundlePath> for RawBundlePath {
+    fn from(bundle_path: BundlePath) -> Self {
+        if bundle_path.filter == PathFilter::default() {
+            RawBundlePath::Plain(bundle_path.path)
+        } else {
+            RawBundlePath::Filtered {
+                path: bundle_path.path,
+                filter: bundle_path.filter,
+            }
+        }
+    }
+}
+
+/// Settings of a single filter of `META-INF/vault/filter.xml`.
+#[derive(Getters, Default, Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+#[getset(get = "pub")]
+pub(crate) struct PathFilter {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mode: Option<FilterMode>,
+
+    /// regexes of JCR paths included in the package
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    include: Vec<String>,
+
+    /// regexes of JCR paths excluded from the package; they take precedence over `include`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    exclude: Vec<String>,
+}
+
+impl PathFilter {
+    pub(crate) fn new(mode: Option<FilterMode>, include: Vec<&str>, exclude: Vec<&str>) -> Self {
+        Self {
+            mode,
+            include: include.into_iter().map(Into::into).collect(),
+            exclude: exclude.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    pub(crate) fn has_rules(&self) -> bool {
+        !self.include.is_empty() || !self.exclude.is_empty()
+    }
+
+    /// Compiles the include and exclude patterns, so they can be checked for many paths.
+    pub(crate) fn rules(&self) -> Result<PathRules> {
+        Ok(PathRules {
+            include: compile_patterns(&self.include)?,
+            exclude: compile_patterns(&self.exclude)?,
+        })
+    }
+}
+
+/// Compiled include and exclude rules of `PathFilter`.
+#[derive(Debug)]
+pub(crate) struct PathRules {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl PathRules {
+    /// Checks if JCR path is covered by the rules, the same way FileVault does it: when
+    /// there are include rules, the path has to match at least one of them.
+    pub(crate) fn covers<S: AsRef<str>>(&self, jcr_path: S) -> bool {
+        let jcr_path = jcr_path.as_ref();
+        let matches_any = |regexes: &[Regex]| regexes.iter().any(|r| r.is_match(jcr_path));
+        let included = self.include.is_empty() || matches_any(&self.include);
+        included && !matches_any(&self.exclude)
+    }
+}
+
+fn compile_patterns(patterns: &[String]) -> Result<Vec<Regex>> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            filter_regex(pattern).with_context(|| format!("incorrect filter pattern '{pattern}'"))
+        })
+        .collect()
+}
+
+/// FileVault matches the whole path with the pattern.
+fn filter_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    Regex::new(&format!("^(?:{pattern})$"))
+}
+
+/// The regex error message points at the error in the following lines, only the reason is kept.
+fn regex_error_reason(e: &regex::Error) -> String {
+    let message = e.to_string();
+    let reason = message.lines().last().unwrap_or_default();
+    reason.trim_start_matches("error: ").to_string()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum FilterMode {
+    Replace,
+    Merge,
+    Update,
+}
+
+impl FilterMode {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            FilterMode::Replace => "replace",
+            FilterMode::Merge => "merge",
+            FilterMode::Update => "update",
+        }
+    }
+}
+
+/// Settings of the HTTP client used to connect to the instance.
+#[derive(Getters, CopyGetters, Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+#[serde(default)]
+pub(crate) struct HttpSettings {
+    /// maximum time (in seconds) for establishing the connection
+    #[getset(get_copy = "pub")]
+    connect_timeout_secs: u64,
+
+    /// maximum time (in seconds) of waiting for data from the instance when reading responses
+    /// of GET requests
+    #[serde(alias = "timeout_secs")]
+    #[getset(get_copy = "pub")]
+    read_timeout_secs: u64,
+
+    /// maximum time (in seconds) of POST requests, i.e. uploads and package commands (e.g.
+    /// install), from sending the request to receiving the response
+    #[getset(get_copy = "pub")]
+    cmd_timeout_secs: u64,
+
+    /// how many times idempotent requests are retried after connection errors, timeouts
+    /// and gateway errors
+    #[getset(get_copy = "pub")]
+    retries: u32,
+
+    /// time (in milliseconds) before the first retry, doubled before each next one
+    #[getset(get_copy = "pub")]
+    retry_interval_ms: u64,
+
+    /// PEM file with additional CA certificates trusted by the client
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[getset(get = "pub")]
+    ca_cert: Option<String>,
+
+    /// accept invalid (e.g. self-signed) certificates
+    #[getset(get_copy = "pub")]
+    insecure: bool,
+}
+
+impl HttpSettings {
+    pub(crate) fn is_default(&self) -> bool {
+        *self == HttpSettings::default()
+    }
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            connect_timeout_secs: 10,
+            read_timeout_secs: 300,
+            cmd_timeout_secs: 3600,
+            retries: 2,
+            retry_interval_ms: 500,
+            ca_cert: None,
+            insecure: false,
+        }
+    }
+}
+
+/// Naming of temporary packages `je` creates on the instance.
+#[derive(Getters, Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+#[serde(default)]
+#[getset(get = "pub")]
+pub(crate) struct PkgSettings {
+    /// group of the packages, also the group cleaned up by `je pkg prune`
+    group: String,
+
+    /// beginning of package names, followed by the purpose of the package (`-pkg`, `-snapshot`)
+    name_prefix: String,
+}
+
+impl PkgSettings {
+    #[cfg(test)]
+    pub(crate) fn new<S: Into<String>>(group: S, name_prefix: S) -> Self {
+        Self {
+            group: group.into(),
+            name_prefix: name_prefix.into(),
+        }
+    }
+
+    pub(crate) fn is_default(&self) -> bool {
+        *self == PkgSettings::default()
+    }
+}
+
+impl Default for PkgSettings {
+    fn default() -> Self {
+        Self {
+            group: "je".into(),
+            name_prefix: "je".into(),
+        }
+    }
+}
+
+/// Controls how long `je` waits for the package to be built before downloading it.
+#[derive(CopyGetters, Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+#[serde(default)]
+#[getset(get_copy = "pub")]
+pub(crate) struct BuildWait {
+    /// maximum time (in seconds) for the package to be built
+    timeout_secs: u64,
+
+    /// time (in milliseconds) between the first two status checks
+    interval_ms: u64,
+
+    /// the interval is doubled after each check, but never exceeds this value (in milliseconds)
+    max_interval_ms: u64,
+}
+
+impl BuildWait {
+    #[cfg(test)]
+    pub(crate) fn new(timeout_secs: u64, interval_ms: u64, max_interval_ms: u64) -> Self {
+        Self {
+            timeout_secs,
+            interval_ms,
+            max_interval_ms,
+        }
+    }
+}
+
+impl Default for BuildWait {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 60,
+            interval_ms: 100,
+            max_interval_ms: 2000,
         }
     }
 }
 
+/// Controls snapshots of local files taken before `get` and `get-bundle` overwrite them.
+#[derive(CopyGetters, Debug, Serialize, Deserialize, Eq, PartialEq, Clone, Copy)]
+#[serde(default)]
+#[getset(get_copy = "pub")]
+pub(crate) struct Backups {
+    /// how many of the most recent snapshots are kept; `0` turns the snapshots off
+    keep: usize,
+}
+
+impl Backups {
+    #[cfg(test)]
+    pub(crate) fn new(keep: usize) -> Self {
+        Self { keep }
+    }
+}
+
+impl Default for Backups {
+    fn default() -> Self {
+        Self { keep: 10 }
+    }
+}
+
+/// Controls packages with remote content saved by `put --snapshot` before it installs local files.
+#[derive(CopyGetters, Debug, Serialize, Deserialize, Eq, PartialEq, Clone, Copy)]
+#[serde(default)]
+#[getset(get_copy = "pub")]
+pub(crate) struct Snapshots {
+    /// how many of the most recent snapshots of each path and profile are kept; the new one is
+    /// always kept, as it was requested explicitly
+    keep: usize,
+}
+
+impl Snapshots {
+    #[cfg(test)]
+    pub(crate) fn new(keep: usize) -> Self {
+        Self { keep }
+    }
+}
+
+impl Default for Snapshots {
+    fn default() -> Self {
+        Self { keep: 10 }
+    }
+}
+
 #[derive(Getters, Default, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub(crate) struct IgnoreProp {
     #[serde(rename = "type")]
@@ -119,19 +858,130 @@ pub(crate) struct IgnoreProp {
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub(crate) enum IgnoreType {
+    /// property written as `name="value"` contains the value
     Contains,
+    /// property written as `name="value"` matches the regex
     Regex,
+    /// qualified name of the property (e.g. `jcr:created`) equals the value
+    Name,
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for IgnoreType {
     fn default() -> Self {
         IgnoreType::Contains
     }
 }
 
+impl IgnoreType {
+    const ALL: [IgnoreType; 3] = [IgnoreType::Contains, IgnoreType::Regex, IgnoreType::Name];
+
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            IgnoreType::Contains => "contains",
+            IgnoreType::Regex => "regex",
+            IgnoreType::Name => "name",
+        }
+    }
+}
+
+impl FromStr for IgnoreType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match IgnoreType::ALL.into_iter().find(|t| t.as_str() == s) {
+            Some(ignore_type) => Ok(ignore_type),
+            None => bail!(
+                "incorrect ignore type '{}', use one of: {}",
+                s,
+                IgnoreType::ALL.map(|t| t.as_str()).join(", ")
+            ),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn test_ignore_type_from_str() {
+        for ignore_type in IgnoreType::ALL {
+            // when
+            let parsed: IgnoreType = ignore_type.as_str().parse().unwrap();
+
+            // then
+            assert_eq!(parsed, ignore_type);
+            let serialized = toml::Value::try_from(&ignore_type).unwrap();
+            assert_eq!(serialized.as_str(), Some(ignore_type.as_str()));
+        }
+        assert_eq!(
+            "exact".parse::<IgnoreType>().unwrap_err().to_string(),
+            "incorrect ignore type 'exact', use one of: contains, regex, name"
+        );
+    }
+
+    #[test]
+    fn test_instance_debug_redacts_password() {
+        // given
+        let instance = Instance::new("author", "http://localhost:4502", "admin", "secret");
+        let from_env = Instance::new("author", "http://localhost:4502", "admin", "env:AEM_PASS");
+
+        // when
+        let debug = format!("{:?}", instance);
+        let debug_from_env = format!("{:?}", from_env);
+
+        // then
+        assert!(!debug.contains("secret"));
+        assert!(debug.contains(r#"pass: "<redacted>""#));
+        assert!(debug_from_env.contains(r#"pass: "env:AEM_PASS""#));
+    }
+
+    #[test]
+    fn test_instance_with_auth() -> Result<()> {
+        // given
+        let cfg = r#"
+            name = "cloud"
+            addr = "https://author.adobeaemcloud.com"
+            auth = { type = "bearer", token = "secret-token" }
+        "#;
+
+        // when
+        let instance: Instance = toml::from_str(cfg)?;
+
+        // then
+        assert_eq!(
+            instance.auth(),
+            &Auth::Bearer {
+                token: "secret-token".into()
+            }
+        );
+        assert!(!format!("{:?}", instance).contains("secret-token"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_instance_with_http_settings() -> Result<()> {
+        // given
+        let cfg = r#"
+            name = "qa"
+            addr = "https://qa.example.com"
+            user = "admin"
+            pass = "admin"
+            http = { read_timeout_secs = 30, ca_cert = "qa-ca.pem" }
+        "#;
+
+        // when
+        let instance: Instance = toml::from_str(cfg)?;
+
+        // then
+        assert_eq!(instance.http().read_timeout_secs(), 30);
+        assert_eq!(instance.http().ca_cert(), &Some("qa-ca.pem".to_string()));
+        assert_eq!(instance.http().retries(), 2);
+        assert!(!instance.http().insecure());
+        Ok(())
+    }
 
     #[test]
     fn test_cfg_default() {
@@ -146,6 +996,11 @@ mod test {
                 "admin",
             )],
             bundles: None,
+            groups: None,
+            build_wait: None,
+            backups: None,
+            snapshots: None,
+            root: PathBuf::new(),
         };
 
         // when
@@ -154,4 +1009,208 @@ mod test {
         // then
         assert_eq!(default_cfg, expected_cfg);
     }
+
+    #[test]
+    fn test_instances() -> Result<()> {
+        // given
+        let cfg = Cfg {
+            profiles: vec![
+                Instance::new("author", "http://localhost:4502", "admin", "admin"),
+                Instance::new("publish", "http://localhost:4503", "admin", "admin"),
+                Instance::new("dispatcher", "http://localhost:8080", "admin", "admin"),
+            ],
+            groups: Some(vec![Group::new("local", vec!["author", "publish"])]),
+            ..Cfg::default()
+        };
+        let names = |selection: Option<&str>| -> Result<Vec<String>> {
+            let instances = cfg.instances(selection.map(String::from).as_ref())?;
+            Ok(instances.iter().map(|i| i.name().clone()).collect())
+        };
+
+        // then
+        assert_eq!(names(None)?, vec!["author"]);
+        assert_eq!(names(Some("publish"))?, vec!["publish"]);
+        assert_eq!(names(Some("publish,author"))?, vec!["publish", "author"]);
+        assert_eq!(names(Some("local"))?, vec!["author", "publish"]);
+        assert_eq!(
+            names(Some("local, dispatcher, author"))?,
+            vec!["author", "publish", "dispatcher"]
+        );
+        assert_eq!(
+            names(Some("author,missing")).unwrap_err().to_string(),
+            "unknown profile or group 'missing', available profiles: author, publish, \
+             dispatcher; available groups: local"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate() -> Result<()> {
+        // given
+        let cfg: Cfg = toml::from_str(
+            r#"
+            version = "0.3.0"
+            ignore_properties = [{ type = "regex", value = "[" }, { type = "name", value = "[" }]
+
+            [[profile]]
+            name = "author"
+            addr = "http://localhost:4502"
+
+            [[profile]]
+            name = "author"
+            addr = "localhost:4503"
+
+            [[profile]]
+            name = "publish"
+            addr = "ftp://localhost"
+
+            [[bundle]]
+            name = "apps"
+            paths = ["ui.apps/jcr_root/apps/site", "ui.apps/apps/other"]
+
+            [[bundle]]
+            name = "dam"
+            paths = [{ path = "ui.content/jcr_root/content/dam", exclude = ["(unclosed"] }]
+
+            [[group]]
+            name = "local"
+            profiles = ["author", "dispatcher"]
+        "#,
+        )?;
+
+        // when
+        let res = cfg.validate();
+
+        // then
+        let err = res.unwrap_err().to_string();
+        let problems: Vec<&str> = err.lines().skip(1).collect();
+        assert_eq!(err.lines().next(), Some("incorrect config:"));
+        assert_eq!(problems.len(), 7);
+        assert_eq!(problems[0], "  - duplicate profile 'author'");
+        assert!(problems[1].starts_with("  - profile 'author': incorrect addr 'localhost:4503'"));
+        assert_eq!(
+            problems[2],
+            "  - profile 'publish': incorrect addr 'ftp://localhost': use http or https scheme"
+        );
+        assert_eq!(
+            problems[3],
+            "  - ignore_properties: incorrect regex '[': unclosed character class"
+        );
+        assert!(problems[4].starts_with("  - bundle 'apps': "));
+        assert!(problems[4].contains("ui.apps/apps/other"));
+        assert_eq!(
+            problems[5],
+            "  - bundle 'dam': incorrect filter pattern '(unclosed': unclosed group"
+        );
+        assert_eq!(
+            problems[6],
+            "  - group 'local': unknown profile 'dispatcher', available profiles: author, publish"
+        );
+        assert!(Cfg::default().validate().is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_selects_many() {
+        // given
+        let cfg = Cfg {
+            groups: Some(vec![Group::new("local", vec!["author", "publish"])]),
+            ..Cfg::default()
+        };
+
+        // then
+        assert!(!cfg.selects_many(None));
+        assert!(!cfg.selects_many(Some(&"author".into())));
+        assert!(cfg.selects_many(Some(&"author,publish".into())));
+        assert!(cfg.selects_many(Some(&"local".into())));
+    }
+
+    #[test]
+    fn test_bundle_paths_with_filters() -> Result<()> {
+        // given
+        let cfg = r#"
+            name = "dam"
+            paths = [
+                "/project/jcr_root/content/dam/plain",
+                { path = "/project/jcr_root/content/dam/site", mode = "merge", exclude = [".*/renditions(/.*)?"] },
+            ]
+        "#;
+        let expected = Bundle {
+            name: "dam".into(),
+            paths: vec![
+                BundlePath::new("/project/jcr_root/content/dam/plain"),
+                BundlePath::with_filter(
+                    "/project/jcr_root/content/dam/site",
+                    PathFilter::new(Some(FilterMode::Merge), vec![], vec![".*/renditions(/.*)?"]),
+                ),
+            ],
+        };
+
+        // when
+        let bundle: Bundle = toml::from_str(cfg)?;
+
+        // then
+        assert_eq!(bundle, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bundle_paths_serialization() -> Result<()> {
+        // given
+        let bundle = Bundle {
+            name: "dam".into(),
+            paths: vec![
+                BundlePath::new("/jcr_root/plain"),
+                BundlePath::with_filter(
+                    "/jcr_root/filtered",
+                    PathFilter::new(Some(FilterMode::Update), vec!["/a/.*"], vec![]),
+                ),
+            ],
+        };
+
+        // when
+        let serialized = toml::to_string(&bundle)?;
+
+        // then
+        assert_eq!(toml::from_str::<Bundle>(&serialized)?, bundle);
+        assert!(serialized.contains(r#"path = "/jcr_root/plain""#));
+        Ok(())
+    }
+
+    #[test]
+    fn test_path_filter_covers() -> Result<()> {
+        // given
+        let exclude_only = PathFilter::new(None, vec![], vec![".*/renditions(/.*)?"]);
+        let include_and_exclude = PathFilter::new(
+            None,
+            vec!["/content/dam/site/images(/.*)?"],
+            vec![".*/renditions(/.*)?"],
+        );
+
+        // when
+        let default = PathFilter::default().rules()?;
+        let exclude_only = exclude_only.rules()?;
+        let include_and_exclude = include_and_exclude.rules()?;
+
+        // then
+        assert!(default.covers("/content/dam/site"));
+        assert!(exclude_only.covers("/content/dam/site/a.png"));
+        assert!(!exclude_only.covers("/content/dam/site/a.png/jcr:content/renditions"));
+        assert!(include_and_exclude.covers("/content/dam/site/images/a.png"));
+        assert!(!include_and_exclude.covers("/content/dam/site/docs/a.pdf"));
+        assert!(!include_and_exclude.covers("/content/dam/site/images/a.png/renditions/x"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_path_filter_rules_with_broken_pattern() {
+        // given
+        let filter = PathFilter::new(None, vec!["["], vec![]);
+
+        // when
+        let res = filter.rules();
+
+        // then
+        assert_eq!(res.unwrap_err().to_string(), "incorrect filter pattern '['");
+    }
 }
@@ -0,0 +1,432 @@
+//! Changes of the config file made by `je config` and `je reinit`. The file is edited in place,
+//! so comments and formatting of the user are kept.
+use crate::cfg::{choices, Cfg, IgnoreType};
+use crate::cfgmgr::CURRENT_VERSION;
+use anyhow::{bail, Context, Result};
+use log::{debug, info};
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::{value, Array, ArrayOfTables, DocumentMut, InlineTable, Item, Table, Value};
+
+const PROFILES: &str = "profile";
+
+const BUNDLES: &str = "bundle";
+
+const IGNORE_PROPERTIES: &str = "ignore_properties";
+
+/// Config file opened for editing.
+#[derive(Debug)]
+pub(crate) struct CfgFile {
+    path: PathBuf,
+    doc: DocumentMut,
+}
+
+impl CfgFile {
+    /// Opens the config file; when it doesn't exist, the default config is edited instead.
+    pub(crate) fn open<P: Into<PathBuf>>(path: P) -> Result<Self> {
+        let path = path.into();
+        let content = if path.exists() {
+            fs::read_to_string(&path)?
+        } else {
+            debug!(
+                "{} doesn't exist, editing the default config",
+                path.display()
+            );
+            toml::to_string(&Cfg::default())?
+        };
+        let doc = content
+            .parse()
+            .context(format!("failed to read config {}", path.display()))?;
+        Ok(Self { path, doc })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Adds the profile; its password is kept in the credentials store, not in the config.
+    pub(crate) fn add_profile(&mut self, name: &str, addr: &str, user: Option<&str>) -> Result<()> {
+        self.ensure_current_format()?;
+        if find(self.tables(PROFILES), name).is_some() {
+            bail!("profile '{}' already exists", name);
+        }
+        let mut profile = Table::new();
+        profile.insert("name", value(name));
+        profile.insert("addr", value(addr));
+        if let Some(user) = user {
+            profile.insert("user", value(user));
+        }
+        self.push_table(PROFILES, profile);
+        Ok(())
+    }
+
+    pub(crate) fn remove_profile(&mut self, name: &str) -> Result<()> {
+        self.remove_table(PROFILES, name)
+    }
+
+    /// Moves the profile to the top of the file, so it's used when `--profile` is not passed.
+    pub(crate) fn set_default_profile(&mut self, name: &str) -> Result<()> {
+        let profiles = self.tables_mut(PROFILES);
+        let idx = match find(profiles.as_deref(), name) {
+            Some(idx) => idx,
+            None => bail!(unknown(profiles.as_deref(), PROFILES, name)),
+        };
+        let profiles = profiles.expect("profile was found, so profiles exist");
+        let mut tables: Vec<Table> = profiles.iter().cloned().collect();
+        // tables are written in the order of their positions, so the positions stay in place
+        // and the tables are moved between them
+        let positions: Vec<Option<usize>> = tables.iter().map(Table::position).collect();
+        let default = tables.remove(idx);
+        tables.insert(0, default);
+        profiles.clear();
+        for (mut table, position) in tables.into_iter().zip(positions) {
+            if let Some(position) = position {
+                table.set_position(position);
+            }
+            profiles.push(table);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn add_bundle(&mut self, name: &str, paths: &[String]) -> Result<()> {
+        self.ensure_current_format()?;
+        if find(self.tables(BUNDLES), name).is_some() {
+            bail!("bundle '{}' already exists", name);
+        }
+        let mut bundle = Table::new();
+        bundle.insert("name", value(name));
+        bundle.insert("paths", value(paths.iter().collect::<Array>()));
+        self.push_table(BUNDLES, bundle);
+        Ok(())
+    }
+
+    pub(crate) fn remove_bundle(&mut self, name: &str) -> Result<()> {
+        self.remove_table(BUNDLES, name)
+    }
+
+    pub(crate) fn add_bundle_path(&mut self, name: &str, path: &str) -> Result<()> {
+        let bundles = self.tables_mut(BUNDLES);
+        let idx = match find(bundles.as_deref(), name) {
+            Some(idx) => idx,
+            None => bail!(unknown(bundles.as_deref(), BUNDLES, name)),
+        };
+        let bundle = bundles
+            .and_then(|bundles| bundles.get_mut(idx))
+            .expect("bundle was found, so it exists");
+        match bundle.get_mut("paths").and_then(Item::as_array_mut) {
+            Some(paths) => paths.push(path),
+            None => bail!("paths of bundle '{}' are not an array", name),
+        }
+        Ok(())
+    }
+
+    pub(crate) fn add_ignore_property(
+        &mut self,
+        ignore_type: &IgnoreType,
+        val: &str,
+    ) -> Result<()> {
+        self.ensure_current_format()?;
+        let mut prop = InlineTable::new();
+        prop.insert("type", ignore_type.as_str().into());
+        prop.insert("value", val.into());
+        match self.ignore_properties()? {
+            Some(props) => props.push(prop),
+            None => {
+                let props: Array = [Value::from(prop)].into_iter().collect();
+                self.doc.insert(IGNORE_PROPERTIES, value(props));
+            }
+        }
+        Ok(())
+    }
+
+    /// Adjusts the config of the old version (ignored properties were plain strings checked with
+    /// `contains`) to the current one and sets the current version.
+    pub(crate) fn migrate(&mut self) -> Result<()> {
+        if self.version().is_none() {
+            info!("adjusting configuration to a newer version");
+            if let Some(props) = self.ignore_properties()? {
+                for prop in props.iter_mut() {
+                    if let Some(old) = prop.as_str() {
+                        let mut new = InlineTable::new();
+                        new.insert("type", "contains".into());
+                        new.insert("value", old.into());
+                        *prop = new.into();
+                    }
+                }
+            }
+        }
+        self.doc.insert("version", value(CURRENT_VERSION));
+        Ok(())
+    }
+
+    /// Writes the file after checking the edited config, so an incorrect change leaves the file
+    /// untouched.
+    pub(crate) fn save(&self) -> Result<()> {
+        let content = self.doc.to_string();
+        let cfg: Cfg = toml::from_str(&content).context("edited config is incorrect")?;
+        cfg.validate()?;
+        info!("writing config {}", self.path.display());
+        fs::write(&self.path, content)?;
+        Ok(())
+    }
+
+    fn version(&self) -> Option<&str> {
+        self.doc.get("version").and_then(Item::as_str)
+    }
+
+    /// New entries are written in the current format, so the old config has to be adjusted first.
+    fn ensure_current_format(&self) -> Result<()> {
+        if self.version().is_none() {
+            bail!("config has older format, use je reinit to adjust it first");
+        }
+        Ok(())
+    }
+
+    fn ignore_properties(&mut self) -> Result<Option<&mut Array>> {
+        match self.doc.get_mut(IGNORE_PROPERTIES) {
+            Some(item) => match item.as_array_mut() {
+                Some(props) => Ok(Some(props)),
+                None => bail!("{} is not an array", IGNORE_PROPERTIES),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn tables(&self, key: &str) -> Option<&ArrayOfTables> {
+        self.doc.get(key).and_then(Item::as_array_of_tables)
+    }
+
+    fn tables_mut(&mut self, key: &str) -> Option<&mut ArrayOfTables> {
+        self.doc.get_mut(key).and_then(Item::as_array_of_tables_mut)
+    }
+
+    fn push_table(&mut self, key: &str, mut table: Table) {
+        // separated from the previous section with an empty line, as `je init` does it
+        table.decor_mut().set_prefix("\n");
+        match self.tables_mut(key) {
+            Some(tables) => tables.push(table),
+            None => {
+                let mut tables = ArrayOfTables::new();
+                tables.push(table);
+                self.doc.insert(key, Item::ArrayOfTables(tables));
+            }
+        }
+    }
+
+    fn remove_table(&mut self, key: &str, name: &str) -> Result<()> {
+        let tables = self.tables_mut(key);
+        match find(tables.as_deref(), name) {
+            Some(idx) => tables.expect("table was found").remove(idx),
+            None => bail!(unknown(tables.as_deref(), key, name)),
+        }
+        Ok(())
+    }
+}
+
+fn unknown(tables: Option<&ArrayOfTables>, key: &str, name: &str) -> String {
+    let names: Vec<String> = tables
+        .into_iter()
+        .flatten()
+        .filter_map(|table| table.get("name").and_then(Item::as_str))
+        .map(String::from)
+        .collect();
+    format!(
+        "unknown {} '{}', {}",
+        key,
+        name,
+        choices(&format!("{}s", key), names.iter())
+    )
+}
+
+/// Index of the table with the `name`.
+fn find(tables: Option<&ArrayOfTables>, name: &str) -> Option<usize> {
+    tables?
+        .iter()
+        .position(|table| table.get("name").and_then(Item::as_str) == Some(name))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::TempDir;
+
+    const CONFIG: &str = r#"# shared with the team
+version = "0.3.0"
+ignore_properties = [{ type = "name", value = "jcr:created" }]
+
+# local author
+[[profile]]
+name = "author"
+addr = "http://localhost:4502"
+user = "admin"
+pass = "admin"
+
+# local publish
+[[profile]]
+name = "publish"
+addr = "http://localhost:4503"  # default port
+user = "admin"
+pass = "admin"
+
+[[bundle]]
+name = "configs"
+paths = ["ui.config/jcr_root/conf/site"]
+"#;
+
+    fn cfg_file(content: &str) -> Result<(TempDir, CfgFile)> {
+        let tmp_dir = TempDir::new()?;
+        let path = tmp_dir.path().join(".je");
+        fs::write(&path, content)?;
+        let cfg_file = CfgFile::open(path)?;
+        Ok((tmp_dir, cfg_file))
+    }
+
+    #[test]
+    fn test_add_and_remove_profile() -> Result<()> {
+        // given
+        let (_tmp_dir, mut cfg_file) = cfg_file(CONFIG)?;
+
+        // when
+        cfg_file.add_profile("dispatcher", "http://localhost:8080", Some("admin"))?;
+        cfg_file.remove_profile("author")?;
+        cfg_file.save()?;
+
+        // then
+        assert_eq!(
+            fs::read_to_string(cfg_file.path())?,
+            r#"# shared with the team
+version = "0.3.0"
+ignore_properties = [{ type = "name", value = "jcr:created" }]
+
+# local publish
+[[profile]]
+name = "publish"
+addr = "http://localhost:4503"  # default port
+user = "admin"
+pass = "admin"
+
+[[profile]]
+name = "dispatcher"
+addr = "http://localhost:8080"
+user = "admin"
+
+[[bundle]]
+name = "configs"
+paths = ["ui.config/jcr_root/conf/site"]
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_default_profile() -> Result<()> {
+        // given
+        let (_tmp_dir, mut cfg_file) = cfg_file(CONFIG)?;
+
+        // when
+        cfg_file.set_default_profile("publish")?;
+        cfg_file.save()?;
+
+        // then
+        let content = fs::read_to_string(cfg_file.path())?;
+        let cfg: Cfg = toml::from_str(&content)?;
+        assert_eq!(cfg.instance(None)?.name(), "publish");
+        assert!(content.contains(
+            "# local publish\n[[profile]]\nname = \"publish\"\naddr = \"http://localhost:4503\"  # \
+             default port"
+        ));
+        assert!(content.ends_with(
+            "[[bundle]]\nname = \"configs\"\npaths = [\"ui.config/jcr_root/conf/site\"]\n"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_edit_bundles_and_ignore_properties() -> Result<()> {
+        // given
+        let (_tmp_dir, mut cfg_file) = cfg_file(CONFIG)?;
+
+        // when
+        cfg_file.add_bundle_path("configs", "ui.apps/jcr_root/apps/site/config")?;
+        cfg_file.add_bundle("dam", &["ui.content/jcr_root/content/dam/site".into()])?;
+        cfg_file.add_ignore_property(&IgnoreType::Regex, ".*Modified.*")?;
+        cfg_file.save()?;
+
+        // then
+        let content = fs::read_to_string(cfg_file.path())?;
+        assert!(content.contains(
+            r#"ignore_properties = [{ type = "name", value = "jcr:created" }, { type = "regex", value = ".*Modified.*" }]"#
+        ));
+        assert!(content.ends_with(
+            r#"[[bundle]]
+name = "configs"
+paths = ["ui.config/jcr_root/conf/site", "ui.apps/jcr_root/apps/site/config"]
+
+[[bundle]]
+name = "dam"
+paths = ["ui.content/jcr_root/content/dam/site"]
+"#
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_rejects_incorrect_change() -> Result<()> {
+        // given
+        let (_tmp_dir, mut cfg_file) = cfg_file(CONFIG)?;
+
+        // when
+        cfg_file.add_bundle("apps", &["ui.apps/apps/site".into()])?;
+        let res = cfg_file.save();
+
+        // then
+        assert!(res
+            .unwrap_err()
+            .to_string()
+            .starts_with("incorrect config:"));
+        assert_eq!(fs::read_to_string(cfg_file.path())?, CONFIG);
+        assert_eq!(
+            cfg_file.remove_profile("missing").unwrap_err().to_string(),
+            "unknown profile 'missing', available profiles: author, publish"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate() -> Result<()> {
+        // given
+        let (_tmp_dir, mut cfg_file) = cfg_file(
+            r#"# old config
+ignore_properties = ["jcr:created", "jcr:createdBy"]
+
+[[profile]]
+name = "author"
+addr = "http://localhost:4502"
+user = "admin"
+pass = "admin"
+"#,
+        )?;
+
+        // when
+        let res = cfg_file.add_ignore_property(&IgnoreType::Name, "jcr:uuid");
+        cfg_file.migrate()?;
+        cfg_file.save()?;
+
+        // then
+        assert!(res.is_err());
+        assert_eq!(
+            fs::read_to_string(cfg_file.path())?,
+            r#"# old config
+ignore_properties = [{ type = "contains", value = "jcr:created" }, { type = "contains", value = "jcr:createdBy" }]
+version = "0.3.0"
+
+[[profile]]
+name = "author"
+addr = "http://localhost:4502"
+user = "admin"
+pass = "admin"
+"#
+        );
+        Ok(())
+    }
+}
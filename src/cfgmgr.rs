@@ -1,13 +1,21 @@
-use crate::cfg::{Cfg, IgnoreProp, IgnoreType, Instance};
-use anyhow::Result;
+use crate::cfg::{Auth, Cfg, IgnoreProp, IgnoreType, Instance, ENV_PREFIX};
+use anyhow::{bail, Context, Result};
 use log::{debug, info};
 use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
-use std::fs::read_to_string;
-use std::path::Path;
+use std::fs::{self, read_to_string};
+use std::path::{self, Path, PathBuf};
+use toml_edit::{value, DocumentMut, Item, Table};
 
 pub(crate) const CONFIG_FILE: &str = ".je";
 
+/// Location of the global config, relative to the XDG config directory.
+const GLOBAL_CONFIG_FILE: &str = "je/config.toml";
+
+/// Location of the credentials store, relative to the home directory.
+const CREDENTIALS_FILE: &str = ".config/je/credentials";
+
 pub(crate) const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -24,23 +32,268 @@ struct Pre030Cfg {
     pub(crate) profiles: Vec<Instance>,
 }
 
-pub(crate) fn handle_cfg_load() -> Result<Cfg> {
-    debug!("loading config: {:?}{}", env::current_dir(), CONFIG_FILE);
-    if Path::new(CONFIG_FILE).exists() {
-        let version: Version = toml::from_str(&read_to_string(CONFIG_FILE)?)?;
-        if version.value.is_none() {
-            let cfg: Pre030Cfg = toml::from_str(&read_to_string(CONFIG_FILE)?)?;
-            Ok(convert_to_current_cfg(cfg))
+/// Config files `je` reads; the project config is merged over the global one.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct CfgFiles {
+    pub(crate) project: Option<PathBuf>,
+    pub(crate) global: Option<PathBuf>,
+}
+
+impl CfgFiles {
+    /// Uses `config` as the project config when it's passed, otherwise the nearest `.je` in
+    /// the first of `starts` (or its parent, when it's a file) or any of their ancestors.
+    /// The global config is used when it exists.
+    pub(crate) fn locate(config: Option<&Path>, starts: &[&Path]) -> Result<Self> {
+        let project = match config {
+            Some(config) if config.is_file() => Some(path::absolute(config)?),
+            Some(config) => bail!("config file {} doesn't exist", config.display()),
+            None => {
+                let mut found = None;
+                for start in starts {
+                    found = path::absolute(start)?
+                        .ancestors()
+                        .map(|dir| dir.join(CONFIG_FILE))
+                        .find(|file| file.is_file());
+                    if found.is_some() {
+                        break;
+                    }
+                }
+                found
+            }
+        };
+        let global = global_cfg_path().filter(|file| file.is_file());
+        debug!("located config files: {:?}, {:?}", project, global);
+        Ok(Self { project, global })
+    }
+
+    /// Directory of the project config.
+    pub(crate) fn project_root(&self) -> Option<&Path> {
+        self.project.as_deref().and_then(Path::parent)
+    }
+}
+
+fn global_cfg_path() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("HOME")
+                .or_else(|| env::var_os("USERPROFILE"))
+                .map(|home| PathBuf::from(home).join(".config"))
+        })
+        .map(|dir| dir.join(GLOBAL_CONFIG_FILE))
+}
+
+/// Loads the config files and resolves credentials of the profiles selected with `--profile`
+/// (see `Cfg::instances`); other profiles are left as they are, so their missing variables or
+/// files don't break commands which don't use them.
+pub(crate) fn handle_cfg_load(files: &CfgFiles, selection: Option<&String>) -> Result<Cfg> {
+    let mut cfg = load_merged_cfg(files)?;
+    // an incorrect selection is reported when the instances are taken from the config
+    let selected: Vec<String> = cfg
+        .instances(selection)
+        .map(|instances| instances.into_iter().map(|i| i.name().clone()).collect())
+        .unwrap_or_default();
+    let store = load_credentials_store(credentials_store_path())?;
+    for profile in cfg
+        .profiles
+        .iter_mut()
+        .filter(|p| selected.contains(p.name()))
+    {
+        resolve_credentials(profile, &store).context(format!(
+            "failed to resolve credentials of profile '{}'",
+            profile.name()
+        ))?;
+    }
+    Ok(cfg)
+}
+
+/// Loads and checks the config files merged together, without resolving credentials.
+pub(crate) fn load_merged_cfg(files: &CfgFiles) -> Result<Cfg> {
+    let mut cfg = match (&files.project, &files.global) {
+        (Some(project), Some(global)) => load_cfg(project)?.merged_over(load_cfg(global)?),
+        (Some(file), None) | (None, Some(file)) => load_cfg(file)?,
+        (None, None) => {
+            debug!("no config file found, loading default");
+            Cfg::default()
+        }
+    };
+    cfg.root = files
+        .project_root()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    cfg.validate()?;
+    Ok(cfg)
+}
+
+/// Loads the config as it's written in the file, without resolving credentials; relative paths
+/// in it are relative to the directory of the file.
+fn load_cfg(file: &Path) -> Result<Cfg> {
+    debug!("loading config: {}", file.display());
+    if file.exists() {
+        let content = read_to_string(file)?;
+        let version: Version = toml::from_str(&content)
+            .context(format!("failed to read config {}", file.display()))?;
+        let mut cfg = if version.value.is_none() {
+            let cfg: Pre030Cfg = toml::from_str(&content)
+                .context(format!("failed to read config {}", file.display()))?;
+            convert_to_current_cfg(cfg)
         } else {
             // new configuration
-            Ok(toml::from_str::<Cfg>(&read_to_string(CONFIG_FILE)?)?)
+            toml::from_str::<Cfg>(&content)
+                .context(format!("failed to read config {}", file.display()))?
+        };
+        if let Some(dir) = file.parent() {
+            cfg.resolve_paths(dir);
         }
+        Ok(cfg)
     } else {
-        debug!("{} config doesn't exists, loading default", CONFIG_FILE);
+        debug!("{} config doesn't exists, loading default", file.display());
         Ok(Cfg::default())
     }
 }
 
+/// Credentials kept outside of the project, keyed by profile name.
+#[derive(Debug, Default, Deserialize, PartialEq, Eq)]
+struct StoredCredentials {
+    user: Option<String>,
+    pass: Option<String>,
+}
+
+fn credentials_store_path() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(CREDENTIALS_FILE))
+}
+
+/// Saves the password of the profile to the credentials store, keeping other entries and
+/// comments of the store; returns path of the store.
+pub(crate) fn store_password(profile: &str, pass: &str) -> Result<PathBuf> {
+    let path = match credentials_store_path() {
+        Some(path) => path,
+        None => bail!("failed to locate the credentials store, HOME is not set"),
+    };
+    save_password(&path, profile, pass)?;
+    Ok(path)
+}
+
+fn save_password(path: &Path, profile: &str, pass: &str) -> Result<()> {
+    let content = if path.exists() {
+        read_to_string(path)?
+    } else {
+        String::new()
+    };
+    let mut store: DocumentMut = content.parse().context(format!(
+        "failed to read credentials from {}",
+        path.display()
+    ))?;
+    let entry = store
+        .entry(profile)
+        .or_insert(Item::Table(Table::new()))
+        .as_table_mut()
+        .context(format!("credentials of '{}' are not a table", profile))?;
+    entry.insert("pass", value(pass));
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    info!("writing credentials store {}", path.display());
+    fs::write(path, store.to_string())?;
+    restrict_to_owner(path)
+}
+
+#[cfg(unix)]
+fn restrict_to_owner(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn restrict_to_owner(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+fn load_credentials_store(path: Option<PathBuf>) -> Result<HashMap<String, StoredCredentials>> {
+    match path {
+        Some(path) if path.exists() => {
+            debug!("loading credentials store {}", path.display());
+            toml::from_str(&read_to_string(&path)?).context(format!(
+                "failed to read credentials from {}",
+                path.display()
+            ))
+        }
+        _ => Ok(HashMap::new()),
+    }
+}
+
+fn resolve_credentials(
+    profile: &mut Instance,
+    store: &HashMap<String, StoredCredentials>,
+) -> Result<()> {
+    let stored = store.get(profile.name());
+    let user = if profile.user().is_empty() {
+        stored.and_then(|c| c.user.clone()).unwrap_or_default()
+    } else {
+        resolve_value(profile.user())?
+    };
+    let pass = match profile.pass_file() {
+        Some(_) if !profile.pass().is_empty() => bail!("both pass and pass_file are set"),
+        Some(pass_file) => read_to_string(pass_file)
+            .context(format!("failed to read password from {}", pass_file))?
+            .trim_end_matches(['\r', '\n'])
+            .to_string(),
+        None if profile.pass().is_empty() => {
+            stored.and_then(|c| c.pass.clone()).unwrap_or_default()
+        }
+        None => resolve_value(profile.pass())?,
+    };
+    let auth = match profile.auth() {
+        Auth::Bearer { token } => Auth::Bearer {
+            token: resolve_value(token)?,
+        },
+        Auth::LoginToken { token } => Auth::LoginToken {
+            token: resolve_value(token)?,
+        },
+        // the file is read once for the selected profile, not before each request
+        Auth::TokenFile { path } => Auth::Bearer {
+            token: read_token_file(path)?,
+        },
+        other => other.clone(),
+    };
+    profile.set_user(user).set_pass(pass).set_auth(auth);
+    Ok(())
+}
+
+/// Reads the token from the file, which contains either the token itself or JSON with
+/// `accessToken` field. Service credentials, which need to be exchanged for a token by IMS,
+/// are not supported.
+fn read_token_file(path: &str) -> Result<String> {
+    let content = read_to_string(path).context(format!("failed to read token from {}", path))?;
+    let content = content.trim();
+    if content.starts_with('{') {
+        let json: serde_json::Value = serde_json::from_str(content)
+            .context(format!("failed to parse token file {}", path))?;
+        match json.get("accessToken").and_then(|t| t.as_str()) {
+            Some(token) => Ok(token.to_string()),
+            None if json.get("integration").is_some() => bail!(
+                "token file {} contains service credentials, which are not supported; \
+                 use the access token generated from them instead",
+                path
+            ),
+            None => bail!("token file {} doesn't contain accessToken", path),
+        }
+    } else {
+        Ok(content.to_string())
+    }
+}
+
+fn resolve_value(value: &str) -> Result<String> {
+    match value.strip_prefix(ENV_PREFIX) {
+        Some(var) => env::var(var).context(format!("environment variable {} is not set", var)),
+        None => Ok(value.to_string()),
+    }
+}
+
 fn convert_to_current_cfg(cfg: Pre030Cfg) -> Cfg {
     info!("adjusting configuration to a newer version");
     let res = Cfg {
@@ -70,6 +323,16 @@ mod test {
     use crate::cfg::{Bundle, IgnoreProp, Instance};
     use crate::testutils::TestConfig;
     use anyhow::Result;
+    use std::fs::{create_dir_all, File};
+    use std::io::prelude::*;
+    use tempfile::TempDir;
+
+    fn project_cfg() -> CfgFiles {
+        CfgFiles {
+            project: Some(PathBuf::from(CONFIG_FILE)),
+            global: None,
+        }
+    }
 
     #[test]
     fn test_handle_cfg_load_when_config_not_exists() -> Result<()> {
@@ -85,7 +348,7 @@ mod test {
         let expected_version = Some("0.3.0".into());
 
         // when
-        let cfg = handle_cfg_load()?;
+        let cfg = handle_cfg_load(&CfgFiles::default(), None)?;
 
         // then
         assert_eq!(cfg.version, expected_version);
@@ -129,7 +392,7 @@ mod test {
         let expected_version = Some("0.3.0".into());
 
         // when
-        let cfg = handle_cfg_load()?;
+        let cfg = handle_cfg_load(&project_cfg(), None)?;
 
         // then
         assert_eq!(cfg.version, expected_version);
@@ -145,7 +408,7 @@ mod test {
     fn test_handle_cfg_load_when_config_is_not_available() -> Result<()> {
         let _ = pretty_env_logger::try_init();
         // when
-        let cfg = handle_cfg_load()?;
+        let cfg = handle_cfg_load(&CfgFiles::default(), None)?;
 
         let expected_profiles = vec![Instance::new(
             "author",
@@ -161,6 +424,493 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_locate_nearest_project_cfg() -> Result<()> {
+        // given
+        let tmp_dir = TempDir::new()?;
+        let project = tmp_dir.path().join("project");
+        let module = project.join("ui.apps");
+        let target = module.join("src/main/content/jcr_root/apps/site/.content.xml");
+        create_dir_all(target.parent().unwrap())?;
+        File::create(project.join(CONFIG_FILE))?;
+        File::create(module.join(CONFIG_FILE))?;
+        let elsewhere = tmp_dir.path().join("elsewhere");
+        create_dir_all(&elsewhere)?;
+
+        // when
+        let from_target = CfgFiles::locate(None, &[&target, tmp_dir.path()])?;
+        let from_fallback = CfgFiles::locate(None, &[&elsewhere, &project])?;
+
+        // then
+        assert_eq!(from_target.project, Some(module.join(CONFIG_FILE)));
+        assert_eq!(from_target.project_root(), Some(module.as_path()));
+        assert_eq!(from_fallback.project, Some(project.join(CONFIG_FILE)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_locate_with_passed_config() -> Result<()> {
+        // given
+        let tmp_dir = TempDir::new()?;
+        let config = tmp_dir.path().join("custom.toml");
+        File::create(&config)?;
+        File::create(tmp_dir.path().join(CONFIG_FILE))?;
+        let missing = tmp_dir.path().join("missing.toml");
+
+        // when
+        let located = CfgFiles::locate(Some(&config), &[tmp_dir.path()])?;
+        let res = CfgFiles::locate(Some(&missing), &[tmp_dir.path()]);
+
+        // then
+        assert_eq!(located.project, Some(config));
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            format!("config file {} doesn't exist", missing.display())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_handle_cfg_load_merges_project_over_global() -> Result<()> {
+        // given
+        let tmp_dir = TempDir::new()?;
+        let global = tmp_dir.path().join("config.toml");
+        File::create(&global)?.write_all(
+            br#"version = "0.3.0"
+               ignore_properties = [{type = "name", value = "jcr:created"}]
+
+               [[profile]]
+               name = "shared"
+               addr = "http://shared:4502"
+               user = "user1"
+               pass = "pass1"
+
+               [[profile]]
+               name = "author"
+               addr = "http://global:4502"
+               user = "user1"
+               pass = "pass1"
+            "#,
+        )?;
+        let project = tmp_dir.path().join(CONFIG_FILE);
+        File::create(&project)?.write_all(
+            br#"version = "0.3.0"
+
+               [[profile]]
+               name = "author"
+               addr = "http://localhost:4502"
+               user = "user2"
+               pass = "pass2"
+            "#,
+        )?;
+        let files = CfgFiles {
+            project: Some(project),
+            global: Some(global),
+        };
+
+        // when
+        let cfg = handle_cfg_load(&files, None)?;
+
+        // then
+        assert_eq!(
+            cfg.profiles,
+            vec![
+                Instance::new("author", "http://localhost:4502", "user2", "pass2"),
+                Instance::new("shared", "http://shared:4502", "user1", "pass1"),
+            ]
+        );
+        assert_eq!(cfg.ignore_properties.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_handle_cfg_load_resolves_only_selected_profiles() -> Result<()> {
+        // given
+        let tmp_dir = TempDir::new()?;
+        let project = tmp_dir.path().join(CONFIG_FILE);
+        File::create(&project)?.write_all(
+            br#"version = "0.3.0"
+
+               [[profile]]
+               name = "author"
+               addr = "http://localhost:4502"
+               user = "user1"
+               pass = "pass1"
+
+               [[profile]]
+               name = "broken"
+               addr = "http://localhost:4503"
+               user = "env:JE_TEST_NOT_SET_USER"
+               pass_file = "missing-pass"
+            "#,
+        )?;
+        let files = CfgFiles {
+            project: Some(project),
+            global: None,
+        };
+
+        // when
+        let cfg = handle_cfg_load(&files, Some(&String::from("author")))?;
+        let res = handle_cfg_load(&files, Some(&String::from("broken")));
+
+        // then
+        assert_eq!(
+            cfg.instance(Some(&String::from("author")))?,
+            Instance::new("author", "http://localhost:4502", "user1", "pass1")
+        );
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "failed to resolve credentials of profile 'broken'"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_handle_cfg_load_reads_token_file_only_of_selected_profile() -> Result<()> {
+        // given
+        let tmp_dir = TempDir::new()?;
+        fs::write(tmp_dir.path().join("token"), "secret-token\n")?;
+        let project = tmp_dir.path().join(CONFIG_FILE);
+        File::create(&project)?.write_all(
+            br#"version = "0.3.0"
+
+               [[profile]]
+               name = "author"
+               addr = "http://localhost:4502"
+               auth = { type = "token_file", path = "token" }
+
+               [[profile]]
+               name = "publish"
+               addr = "http://localhost:4503"
+               auth = { type = "token_file", path = "missing-token" }
+            "#,
+        )?;
+        let files = CfgFiles {
+            project: Some(project),
+            global: None,
+        };
+
+        // when
+        let cfg = handle_cfg_load(&files, None)?;
+
+        // then
+        let author = cfg.instance(Some(&String::from("author")))?;
+        assert_eq!(
+            author.auth(),
+            &Auth::Bearer {
+                token: "secret-token".into()
+            }
+        );
+        let publish = cfg.instance(Some(&String::from("publish")))?;
+        assert!(matches!(publish.auth(), Auth::TokenFile { .. }));
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_merged_cfg_resolves_paths_against_config_dirs() -> Result<()> {
+        // given
+        let tmp_dir = TempDir::new()?;
+        let global_dir = tmp_dir.path().join("global");
+        let project_dir = tmp_dir.path().join("project");
+        create_dir_all(&global_dir)?;
+        create_dir_all(&project_dir)?;
+        let global = global_dir.join("config.toml");
+        File::create(&global)?.write_all(
+            br#"version = "0.3.0"
+
+               [[profile]]
+               name = "qa"
+               addr = "https://qa:443"
+               pass_file = "qa-pass"
+               http = { ca_cert = "qa-ca.pem" }
+            "#,
+        )?;
+        let project = project_dir.join(CONFIG_FILE);
+        File::create(&project)?.write_all(
+            br#"version = "0.3.0"
+
+               [[profile]]
+               name = "author"
+               addr = "http://localhost:4502"
+               auth = { type = "token_file", path = "/abs/token.json" }
+
+               [[bundle]]
+               name = "apps"
+               paths = ["ui.apps/jcr_root/apps"]
+            "#,
+        )?;
+        let files = CfgFiles {
+            project: Some(project),
+            global: Some(global),
+        };
+
+        // when
+        let cfg = load_merged_cfg(&files)?;
+
+        // then
+        assert_eq!(cfg.root, project_dir);
+        let qa = cfg.instance(Some(&"qa".to_string()))?;
+        let in_global = |file: &str| Some(global_dir.join(file).display().to_string());
+        assert_eq!(qa.pass_file(), &in_global("qa-pass"));
+        assert_eq!(qa.http().ca_cert(), &in_global("qa-ca.pem"));
+        let author = cfg.instance(None)?;
+        assert_eq!(
+            author.auth(),
+            &Auth::TokenFile {
+                path: "/abs/token.json".into()
+            }
+        );
+        let bundle = cfg.bundle("apps")?;
+        assert_eq!(
+            bundle.paths()[0].path(),
+            &project_dir
+                .join("ui.apps/jcr_root/apps")
+                .display()
+                .to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_credentials_from_env() -> Result<()> {
+        // given
+        env::set_var("JE_TEST_RESOLVE_USER", "env-user");
+        env::set_var("JE_TEST_RESOLVE_PASS", "env-pass");
+        let mut profile = Instance::new(
+            "author",
+            "http://localhost:4502",
+            "env:JE_TEST_RESOLVE_USER",
+            "env:JE_TEST_RESOLVE_PASS",
+        );
+
+        // when
+        resolve_credentials(&mut profile, &HashMap::new())?;
+
+        // then
+        assert_eq!(profile.user(), "env-user");
+        assert_eq!(profile.pass(), "env-pass");
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_credentials_with_missing_env() {
+        // given
+        let mut profile = Instance::new(
+            "author",
+            "http://localhost:4502",
+            "admin",
+            "env:JE_TEST_NOT_EXISTING_VAR",
+        );
+
+        // when
+        let res = resolve_credentials(&mut profile, &HashMap::new());
+
+        // then
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "environment variable JE_TEST_NOT_EXISTING_VAR is not set"
+        );
+    }
+
+    #[test]
+    fn test_resolve_credentials_from_pass_file() -> Result<()> {
+        // given
+        let tmp_dir = TempDir::new()?;
+        let pass_file = tmp_dir.path().join("pass");
+        File::create(&pass_file)?.write_all(b"file-pass\n")?;
+        let mut profile: Instance = toml::from_str(&format!(
+            r#"
+            name = "author"
+            addr = "http://localhost:4502"
+            user = "admin"
+            pass_file = '{}'
+        "#,
+            pass_file.display()
+        ))?;
+
+        // when
+        resolve_credentials(&mut profile, &HashMap::new())?;
+
+        // then
+        assert_eq!(profile.pass(), "file-pass");
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_credentials_with_pass_and_pass_file() -> Result<()> {
+        // given
+        let mut profile: Instance = toml::from_str(
+            r#"
+            name = "author"
+            addr = "http://localhost:4502"
+            user = "admin"
+            pass = "admin"
+            pass_file = "pass"
+        "#,
+        )?;
+
+        // when
+        let res = resolve_credentials(&mut profile, &HashMap::new());
+
+        // then
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "both pass and pass_file are set"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_credentials_from_store() -> Result<()> {
+        // given
+        let tmp_dir = TempDir::new()?;
+        let store_path = tmp_dir.path().join("credentials");
+        File::create(&store_path)?.write_all(
+            br#"
+            [author]
+            user = "store-user"
+            pass = "store-pass"
+
+            [publish]
+            pass = "publish-pass"
+        "#,
+        )?;
+        let store = load_credentials_store(Some(store_path))?;
+        let mut author: Instance = toml::from_str(
+            r#"
+            name = "author"
+            addr = "http://localhost:4502"
+        "#,
+        )?;
+        let mut publish = Instance::new("publish", "http://localhost:4503", "admin", "");
+
+        // when
+        resolve_credentials(&mut author, &store)?;
+        resolve_credentials(&mut publish, &store)?;
+
+        // then
+        assert_eq!(author.user(), "store-user");
+        assert_eq!(author.pass(), "store-pass");
+        assert_eq!(publish.user(), "admin");
+        assert_eq!(publish.pass(), "publish-pass");
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_credentials_with_token_from_env() -> Result<()> {
+        // given
+        env::set_var("JE_TEST_RESOLVE_TOKEN", "env-token");
+        let mut profile: Instance = toml::from_str(
+            r#"
+            name = "cloud"
+            addr = "https://author.adobeaemcloud.com"
+            auth = { type = "bearer", token = "env:JE_TEST_RESOLVE_TOKEN" }
+        "#,
+        )?;
+
+        // when
+        resolve_credentials(&mut profile, &HashMap::new())?;
+
+        // then
+        assert_eq!(
+            profile.auth(),
+            &Auth::Bearer {
+                token: "env-token".into()
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_credentials_with_token_file() -> Result<()> {
+        // given
+        let tmp_dir = TempDir::new()?;
+        let token_file = tmp_dir.path().join("token.json");
+        File::create(&token_file)?.write_all(br#"{"accessToken": "file-token"}"#)?;
+        let mut profile: Instance = toml::from_str(&format!(
+            r#"
+            name = "cloud"
+            addr = "https://author.adobeaemcloud.com"
+            auth = {{ type = "token_file", path = '{}' }}
+        "#,
+            token_file.display()
+        ))?;
+
+        // when
+        resolve_credentials(&mut profile, &HashMap::new())?;
+
+        // then
+        assert_eq!(
+            profile.auth(),
+            &Auth::Bearer {
+                token: "file-token".into()
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_token_file() -> Result<()> {
+        // given
+        let tmp_dir = TempDir::new()?;
+        let plain = tmp_dir.path().join("token");
+        File::create(&plain)?.write_all(b"plain-token\n")?;
+        let json = tmp_dir.path().join("token.json");
+        File::create(&json)?
+            .write_all(br#"{"ok": true, "statusCode": 200, "accessToken": "json-token"}"#)?;
+        let broken = tmp_dir.path().join("broken.json");
+        File::create(&broken)?.write_all(br#"{"ok": true}"#)?;
+        let service = tmp_dir.path().join("service.json");
+        File::create(&service)?
+            .write_all(br#"{"ok": true, "integration": {"imsEndpoint": "ims"}}"#)?;
+
+        // then
+        assert_eq!(read_token_file(plain.to_str().unwrap())?, "plain-token");
+        assert_eq!(read_token_file(json.to_str().unwrap())?, "json-token");
+        assert!(read_token_file(broken.to_str().unwrap()).is_err());
+        assert!(read_token_file(service.to_str().unwrap())
+            .unwrap_err()
+            .to_string()
+            .contains("service credentials, which are not supported"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_password() -> Result<()> {
+        // given
+        let tmp_dir = TempDir::new()?;
+        let path = tmp_dir.path().join("je").join("credentials");
+        create_dir_all(tmp_dir.path().join("je"))?;
+        File::create(&path)?
+            .write_all(b"# local instances\n[author]\nuser = \"admin\"\npass = \"old\"\n")?;
+
+        // when
+        save_password(&path, "author", "new")?;
+        save_password(&path, "publish", "secret")?;
+
+        // then
+        let content = read_to_string(&path)?;
+        assert!(content.starts_with("# local instances\n"));
+        let store = load_credentials_store(Some(path))?;
+        assert_eq!(store["author"].user.as_deref(), Some("admin"));
+        assert_eq!(store["author"].pass.as_deref(), Some("new"));
+        assert_eq!(store["publish"].pass.as_deref(), Some("secret"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_credentials_store_when_missing() -> Result<()> {
+        // given
+        let tmp_dir = TempDir::new()?;
+
+        // when
+        let store = load_credentials_store(Some(tmp_dir.path().join("credentials")))?;
+
+        // then
+        assert!(store.is_empty());
+        Ok(())
+    }
+
     #[test]
     fn test_instance_with_existing_profile() -> Result<()> {
         let _ = pretty_env_logger::try_init();
@@ -181,8 +931,8 @@ mod test {
         let expected_instance = Instance::new("author", "http://localhost:4502", "user1", "pass1");
 
         // when
-        let cfg = handle_cfg_load()?;
-        let instance = cfg.instance(Some(&String::from("author")));
+        let cfg = handle_cfg_load(&project_cfg(), None)?;
+        let instance = cfg.instance(Some(&String::from("author")))?;
 
         // then
         assert_eq!(instance, expected_instance);
@@ -207,14 +957,16 @@ mod test {
                pass = "pass1"
             "#;
         test_config.write_all(original_cfg_content)?;
-        let default_instance = Instance::new("author", "http://localhost:4502", "admin", "admin");
 
         // when
-        let cfg = handle_cfg_load()?;
-        let instance = cfg.instance(Some(&String::from("not-existing")));
+        let cfg = handle_cfg_load(&project_cfg(), None)?;
+        let res = cfg.instance(Some(&String::from("not-existing")));
 
         // then
-        assert_eq!(instance, default_instance);
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "unknown profile 'not-existing', available profiles: author"
+        );
         // config contains current version so it shouldn't be rewritten
         assert_eq!(original_cfg_content, test_config.read_all()?);
         Ok(())
@@ -246,8 +998,8 @@ mod test {
         let first_instance = Instance::new("publish", "http://localhost:4503", "user2", "pass2");
 
         // when
-        let cfg = handle_cfg_load()?;
-        let instance = cfg.instance(None);
+        let cfg = handle_cfg_load(&project_cfg(), None)?;
+        let instance = cfg.instance(None)?;
 
         // then
         assert_eq!(instance, first_instance);
@@ -273,16 +1025,16 @@ mod test {
 
                [[bundle]]
                name = "simple"
-               paths = ["file1", "file2"]
+               paths = ["jcr_root/file1", "jcr_root/file2"]
             "#;
         test_config.write_all(original_cfg_content)?;
-        let expected_bundle = Bundle::new("simple", vec!["file1", "file2"]);
+        let expected_bundle = Bundle::new("simple", vec!["jcr_root/file1", "jcr_root/file2"]);
 
         // when
-        let cfg = handle_cfg_load();
+        let cfg = handle_cfg_load(&project_cfg(), None);
         debug!("result: {:?}", cfg);
-        let cfg = handle_cfg_load()?;
-        let bundle = cfg.bundle(Some("simple"));
+        let cfg = handle_cfg_load(&project_cfg(), None)?;
+        let bundle = cfg.bundle("simple")?;
 
         // then
         assert_eq!(expected_bundle, bundle);
@@ -308,20 +1060,21 @@ mod test {
 
                [[bundle]]
                name = "simple"
-               paths = ["file1", "file2"]
+               paths = ["jcr_root/file1", "jcr_root/file2"]
 
                [[bundle]]
                name = "other"
-               paths = ["file3", "file4"]
+               paths = ["jcr_root/file3", "jcr_root/file4"]
             "#;
         test_config.write_all(original_cfg_content)?;
-        let expected_simple_bundle = Bundle::new("simple", vec!["file1", "file2"]);
-        let expected_other_bundle = Bundle::new("other", vec!["file3", "file4"]);
+        let expected_simple_bundle =
+            Bundle::new("simple", vec!["jcr_root/file1", "jcr_root/file2"]);
+        let expected_other_bundle = Bundle::new("other", vec!["jcr_root/file3", "jcr_root/file4"]);
 
         // when
-        let cfg = handle_cfg_load()?;
-        let simple_bundle = cfg.bundle(Some("simple"));
-        let other_bundle = cfg.bundle(Some("other"));
+        let cfg = handle_cfg_load(&project_cfg(), None)?;
+        let simple_bundle = cfg.bundle("simple")?;
+        let other_bundle = cfg.bundle("other")?;
 
         // then
         assert_eq!(expected_simple_bundle, simple_bundle);
@@ -347,14 +1100,16 @@ mod test {
                pass = "pass1"
             "#;
         test_config.write_all(original_cfg_content)?;
-        let expected_bundle = Bundle::default();
 
         // when
-        let cfg = handle_cfg_load()?;
-        let bundle = cfg.bundle(Some("not-existing"));
+        let cfg = handle_cfg_load(&project_cfg(), None)?;
+        let res = cfg.bundle("not-existing");
 
         // then
-        assert_eq!(expected_bundle, bundle);
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "unknown bundle 'not-existing', no bundles are defined"
+        );
         // config contains current version so it shouldn't be rewritten
         assert_eq!(original_cfg_content, test_config.read_all()?);
         Ok(())
@@ -379,12 +1134,12 @@ mod test {
                    pass = "pass1"
 
                    [[bundle]]
-                   paths = ["file3", "file4"]
+                   paths = ["jcr_root/file3", "jcr_root/file4"]
                 "#,
             )
             .unwrap();
 
         // when
-        let _not_important = handle_cfg_load().unwrap(); // should panic
+        let _not_important = handle_cfg_load(&project_cfg(), None).unwrap(); // should panic
     }
 }
@@ -1,21 +1,38 @@
-use crate::args::{GetArgs, GetBundleArgs, PutArgs};
-use crate::cfg::Cfg;
-use crate::cfgmgr::{handle_cfg_load, Version, CONFIG_FILE};
+use crate::args::{
+    Age, CatArgs, CatFormat, DiffArgs, GetArgs, GetBundleArgs, LsArgs, PkgArgs, PutArgs,
+    PutBundleArgs, RmArgs, RollbackArgs, WatchArgs,
+};
+use crate::backup::{self, BACKUPS_DIR};
+use crate::cfg::{BuildWait, Bundle, Cfg, IgnoreType, Instance, PkgSettings};
+use crate::cfgedit::CfgFile;
+use crate::cfgmgr::{
+    handle_cfg_load, load_merged_cfg, store_password, CfgFiles, Version, CONFIG_FILE,
+};
+use crate::diff;
 use crate::fsops;
-use crate::http::AemClient;
+use crate::http::{AemClient, Client};
+use crate::jcr;
 use crate::path::Path;
 use crate::pkg;
 use crate::pkgdir;
 use crate::pkgmgr;
-use anyhow::Result;
+use crate::platform::{self, CONTENT_XML};
+use crate::snapshot::{self, Snapshot, SNAPSHOTS_DIR};
+use crate::watch;
+use anyhow::{anyhow, bail, Result};
 use fs_extra::{dir, dir::CopyOptions as DirOpts};
 use fs_extra::{file, file::CopyOptions as FileOpts};
 use log::{debug, info};
+use notify::{RecursiveMode, Watcher};
+use std::any::Any;
+use std::env;
 use std::fs::{self, read_to_string, OpenOptions};
-use std::io::prelude::*;
+use std::io::{self, prelude::*};
 use std::path::{Path as OsPath, PathBuf};
+use std::process::ExitCode;
+use std::sync::mpsc::channel;
 use std::thread;
-use std::time::Duration;
+use std::time::{SystemTime, UNIX_EPOCH};
 use structopt::StructOpt;
 use tempfile::TempDir;
 
@@ -36,10 +53,18 @@ pub(crate) struct Opt {
     #[structopt(short, long)]
     pub(crate) debug: bool,
 
-    /// Profile selection.
+    /// Profile selection; `put` accepts comma-separated profile and group names.
     #[structopt(short, long)]
     pub(crate) profile: Option<String>,
 
+    /// Shows what would be changed (on the instance or in local files) without changing it
+    #[structopt(long, global = true)]
+    pub(crate) dry_run: bool,
+
+    /// Config file used instead of the nearest `.je` in the directory of the path or above it
+    #[structopt(long, global = true, parse(from_os_str))]
+    pub(crate) config: Option<PathBuf>,
+
     #[structopt(subcommand)]
     pub(crate) cmd: Cmd,
 }
@@ -50,6 +75,8 @@ impl Default for Opt {
             verbose: 0,
             debug: false,
             profile: None,
+            dry_run: false,
+            config: None,
             cmd: Cmd::Get {
                 path: String::new(),
             },
@@ -69,24 +96,242 @@ pub(crate) enum Cmd {
         /// name of the bundle
         name: String,
     },
+    /// Shows differences between content on AEM instance and local file system;
+    /// exits with 1 when differences are found and with 2 on errors
+    Diff {
+        /// path to compare
+        path: String,
+    },
+    /// Lists nodes under the path on AEM instance with their primary types
+    Ls {
+        /// JCR path or local path inside of jcr_root directory
+        path: String,
+        /// how many levels of nodes are listed
+        #[structopt(long, default_value = "1")]
+        depth: u32,
+    },
+    /// Prints properties of the node on AEM instance without changing local files
+    Cat {
+        /// JCR path or local path inside of jcr_root directory
+        path: String,
+        /// output format: json (properties from Sling GET servlet) or xml (.content.xml
+        /// downloaded in a package)
+        #[structopt(long, default_value = "json")]
+        format: CatFormat,
+    },
     /// Uploads content to AEM instance
     Put {
         /// path to upload
         path: String,
+        /// download a package with the remote content first, so it can be restored with rollback
+        #[structopt(long)]
+        snapshot: bool,
+    },
+    /// Installs back the most recent snapshot of the path saved by put for the profile
+    Rollback {
+        /// path which was uploaded
+        path: String,
     },
+    /// Uploads bundle (pack of crx paths) defined in config file as a single package
+    PutBundle {
+        /// name of the bundle
+        name: String,
+    },
+    /// Deletes the node with all its descendants from AEM instance
+    Rm {
+        /// JCR path or local path inside of jcr_root directory
+        path: String,
+        /// do not ask for confirmation
+        #[structopt(short, long)]
+        yes: bool,
+    },
+    /// Manages packages on AEM instance
+    Pkg(PkgCmd),
+    /// Manages the configuration file
+    Config(ConfigCmd),
+    /// Watches local directory and uploads changed files to AEM instance
+    Watch {
+        /// directory to watch
+        path: String,
+
+        /// time (in milliseconds) without file changes after which the changes are uploaded
+        #[structopt(long, default_value = "500")]
+        debounce: u64,
+    },
+    /// Restores local files overwritten by the last get or get-bundle
+    Undo,
     /// Initializes configuration file
     Init,
-    /// Rewrites the configuration file with newest version
+    /// Adjusts the configuration file to the newest version, keeping its comments
     Reinit,
 }
 
-pub(crate) fn handle(opt: &Opt, w: &mut impl Write) -> Result<()> {
-    match &opt.cmd {
+#[derive(Debug, PartialEq, Eq, StructOpt, Clone)]
+pub(crate) enum PkgCmd {
+    /// Lists packages with their groups, versions and installation status
+    List {
+        /// show only packages of the group
+        #[structopt(long)]
+        group: Option<String>,
+    },
+    /// Downloads the package to local file system
+    Download {
+        /// package id (group:name:version) or path (e.g. my_packages/site-1.0.zip)
+        package: String,
+        /// file to write the package to, by default the package file name in current directory
+        #[structopt(short, long)]
+        output: Option<String>,
+    },
+    /// Uploads the local package zip
+    Upload {
+        /// package zip
+        file: String,
+        /// install the package after uploading it
+        #[structopt(long)]
+        install: bool,
+        /// replace the package when it's already uploaded
+        #[structopt(long)]
+        force: bool,
+    },
+    /// Installs the package
+    Install {
+        /// package id (group:name:version) or path (e.g. my_packages/site-1.0.zip)
+        package: String,
+    },
+    /// Uninstalls the package, restoring content from before its installation
+    Uninstall {
+        /// package id (group:name:version) or path (e.g. my_packages/site-1.0.zip)
+        package: String,
+    },
+    /// Deletes the package
+    Delete {
+        /// package id (group:name:version) or path (e.g. my_packages/site-1.0.zip)
+        package: String,
+    },
+    /// Deletes packages left by interrupted `je` runs in the profile's package group
+    Prune {
+        /// delete only packages older than this (e.g. 30m, 12h, 7d)
+        #[structopt(long, default_value = "1d")]
+        older_than: Age,
+    },
+}
+
+impl Cmd {
+    /// Local or JCR path the command works on.
+    fn target(&self) -> Option<&String> {
+        match self {
+            Cmd::Get { path }
+            | Cmd::Diff { path }
+            | Cmd::Ls { path, .. }
+            | Cmd::Cat { path, .. }
+            | Cmd::Put { path, .. }
+            | Cmd::Rollback { path }
+            | Cmd::Rm { path, .. }
+            | Cmd::Watch { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, StructOpt, Clone)]
+pub(crate) enum ConfigCmd {
+    /// Checks the configuration (and the `--profile` selection) without running any command
+    Check,
+    /// Prints the configuration merged with the global one, with secrets redacted
+    Show,
+    /// Adds, removes or sets the default profile
+    Profile(ProfileCmd),
+    /// Adds, removes or changes a bundle
+    Bundle(BundleCmd),
+    /// Adds ignored properties
+    Ignore(IgnoreCmd),
+}
+
+#[derive(Debug, PartialEq, Eq, StructOpt, Clone)]
+pub(crate) enum ProfileCmd {
+    /// Adds the profile
+    Add {
+        /// name of the profile
+        name: String,
+        /// address of the instance, e.g. http://localhost:4502
+        addr: String,
+        /// user, by default taken from the credentials store
+        #[structopt(long)]
+        user: Option<String>,
+        /// password, saved to the credentials store instead of the config, which is usually
+        /// committed
+        #[structopt(long)]
+        pass: Option<String>,
+    },
+    /// Removes the profile
+    Remove {
+        /// name of the profile
+        name: String,
+    },
+    /// Moves the profile to the top of the config, so it's used when --profile is not passed
+    SetDefault {
+        /// name of the profile
+        name: String,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq, StructOpt, Clone)]
+pub(crate) enum BundleCmd {
+    /// Adds the bundle
+    Add {
+        /// name of the bundle
+        name: String,
+        /// paths inside of jcr_root directories, relative to the project root
+        #[structopt(required = true)]
+        paths: Vec<String>,
+    },
+    /// Removes the bundle
+    Remove {
+        /// name of the bundle
+        name: String,
+    },
+    /// Adds the path to the bundle
+    AddPath {
+        /// name of the bundle
+        name: String,
+        /// path inside of jcr_root directory, relative to the project root
+        path: String,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq, StructOpt, Clone)]
+pub(crate) enum IgnoreCmd {
+    /// Adds the ignored property
+    Add {
+        /// how properties are matched: contains, regex or name
+        ignore_type: IgnoreType,
+        /// value compared with properties
+        value: String,
+    },
+}
+
+/// Exit code of `diff` when differences are found, the same as of diff(1).
+const DIFFERS: u8 = 1;
+
+/// Exit code of failed commands; it differs from `DIFFERS`, so scripts can tell them apart.
+pub(crate) const ERROR: u8 = 2;
+
+pub(crate) fn handle(opt: &Opt, w: &mut impl Write) -> Result<ExitCode> {
+    let cmd = &opt.cmd;
+    match cmd {
         Cmd::Init => init(&Cfg::default())?,
-        other => {
-            if OsPath::new(CONFIG_FILE).exists() && *other != Cmd::Reinit {
-                // if not Reinit, print warning message
-                let version: Version = toml::from_str(&read_to_string(CONFIG_FILE)?)?;
+        Cmd::Reinit => reinit(opt)?,
+        Cmd::Undo if opt.dry_run => bail!("--dry-run is not supported by this command"),
+        Cmd::Undo => {
+            let cfg_files = locate_cfg(cmd, opt.config.as_deref())?;
+            undo(cfg_files.project_root().unwrap_or(OsPath::new("")), w)?
+        }
+        Cmd::Config(config_cmd) => config(config_cmd, opt, w)?,
+        _ => {
+            let cfg_files = locate_cfg(cmd, opt.config.as_deref())?;
+            if let Some(project_cfg) = &cfg_files.project {
+                // print warning message for old config
+                let version: Version = toml::from_str(&read_to_string(project_cfg)?)?;
                 if version.value.is_none() {
                     // old, not versioned configuration
                     write!(
@@ -100,18 +345,74 @@ pub(crate) fn handle(opt: &Opt, w: &mut impl Write) -> Result<()> {
                     )?;
                 }
             }
-            let cfg = handle_cfg_load()?;
+            let without_dry_run = matches!(
+                cmd,
+                Cmd::GetBundle { .. }
+                    | Cmd::Watch { .. }
+                    | Cmd::Pkg(
+                        PkgCmd::Download { .. }
+                            | PkgCmd::Upload { .. }
+                            | PkgCmd::Uninstall { .. }
+                            | PkgCmd::Delete { .. }
+                    )
+            );
+            if opt.dry_run && without_dry_run {
+                bail!("--dry-run is not supported by this command");
+            }
+            let cfg = handle_cfg_load(&cfg_files, opt.profile.as_ref())?;
             debug!("read config: {:#?}", cfg);
-            match other {
-                Cmd::Get { path } => get(&GetArgs::new(path, cfg, opt))?,
-                Cmd::GetBundle { name } => get_bundle(&GetBundleArgs::new(name, cfg, opt))?,
-                Cmd::Put { path } => put(&PutArgs::new(path, &cfg, opt))?,
-                Cmd::Reinit => init(&cfg)?,
-                Cmd::Init => unreachable!("This code branch will never be executed"),
+            if !matches!(cmd, Cmd::Put { .. }) && cfg.selects_many(opt.profile.as_ref()) {
+                bail!("only put supports selecting many profiles");
+            }
+            match &cmd {
+                Cmd::Get { path } => get(&GetArgs::new(path, cfg, opt)?, w)?,
+                Cmd::GetBundle { name } => get_bundle(&GetBundleArgs::new(name, cfg, opt)?)?,
+                Cmd::Diff { path } => return diff(&DiffArgs::new(path, cfg, opt)?, w),
+                Cmd::Ls { path, depth } => ls(&LsArgs::new(path, *depth, &cfg, opt)?, w)?,
+                Cmd::Cat { path, format } => cat(&CatArgs::new(path, *format, cfg, opt)?, w)?,
+                Cmd::Put { path, snapshot } => {
+                    return put(&PutArgs::new(path, *snapshot, &cfg, opt)?, w)
+                }
+                Cmd::Rollback { path } => rollback(&RollbackArgs::new(path, &cfg, opt)?, w)?,
+                Cmd::Rm { path, yes } => rm(
+                    &RmArgs::new(path, *yes, &cfg, opt)?,
+                    &mut io::stdin().lock(),
+                    w,
+                )?,
+                Cmd::PutBundle { name } => put_bundle(&PutBundleArgs::new(name, &cfg, opt)?, w)?,
+                Cmd::Pkg(cmd) => pkg(cmd, &PkgArgs::new(&cfg, opt)?, w)?,
+                Cmd::Watch { path, debounce } => {
+                    watch(&WatchArgs::new(path, *debounce, &cfg, opt)?, w)?
+                }
+                Cmd::Init | Cmd::Reinit | Cmd::Undo | Cmd::Config(_) => {
+                    unreachable!("This code branch will never be executed")
+                }
             }
         }
     }
-    Ok(())
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Locates config files of the project the command works on, so `je` can be started anywhere
+/// inside of it; bundle paths, backups and snapshots are relative to the project root.
+fn locate_cfg(cmd: &Cmd, config: Option<&OsPath>) -> Result<CfgFiles> {
+    let current_dir = env::current_dir()?;
+    // the search starts at local paths inside of `jcr_root` (JCR paths don't exist locally),
+    // then falls back to the current directory
+    let target_path = cmd
+        .target()
+        .filter(|path| Path::new(path.as_str()).content().is_ok())
+        .map(|path| current_dir.join(path));
+    let starts: Vec<&OsPath> = target_path
+        .iter()
+        .map(PathBuf::as_path)
+        .chain([current_dir.as_path()])
+        .collect();
+    let cfg_files = CfgFiles::locate(config, &starts)?;
+    if let Some(root) = cfg_files.project_root() {
+        info!("using project root {}", root.display());
+    }
+    Ok(cfg_files)
 }
 
 pub(crate) fn init(cfg: &Cfg) -> Result<()> {
@@ -125,63 +426,673 @@ pub(crate) fn init(cfg: &Cfg) -> Result<()> {
     Ok(())
 }
 
-pub(crate) fn get(args: &GetArgs) -> Result<()> {
+pub(crate) fn get(args: &GetArgs, w: &mut impl Write) -> Result<()> {
     info!("executing 'get {}'", args.path().full());
-    let pkg = pkgdir::Pkg::default();
+    let pkg = pkgdir::Pkg::temporary(args.instance().pkg());
     let tmp_dir = pkgdir::mksimple(args.path(), &pkg)?;
-    let client = AemClient::new(args.instance());
-    pkg::zip_pkg(&tmp_dir)?;
-    pkgmgr::upload_pkg(&client, &tmp_dir)?;
-    pkgmgr::build_pkg(&client, &pkg)?;
-    thread::sleep(Duration::from_millis(100));
-    pkgdir::clean(&tmp_dir)?;
-    pkgmgr::download_pkg(&client, &tmp_dir, &pkg)?;
-    pkgmgr::delete_pkg(&client, args.debug(), &pkg)?;
-    pkg::unzip_pkg(&tmp_dir)?;
+    let client = AemClient::new(args.instance())?;
+    fetch_pkg(&client, &tmp_dir, &pkg, args.debug(), args.build_wait())?;
     fsops::cleanup_files(args.ignore_properties(), &tmp_dir)?;
+    if args.dry_run() {
+        let remote = tmp_dir.path().join(args.path().after_root()?);
+        return write_changes(&diff::changed_files(remote, args.path().full())?, w);
+    }
+    backup::snapshot(
+        &args.root().join(BACKUPS_DIR),
+        &[args.path().full()],
+        args.backups().keep(),
+    )?;
     fsops::mv_files_back(&tmp_dir, args.path())?;
     Ok(())
 }
 
-pub(crate) fn put(args: &PutArgs) -> Result<()> {
+pub(crate) fn diff(args: &DiffArgs, w: &mut impl Write) -> Result<ExitCode> {
+    info!("executing 'diff {}'", args.path().full());
+    let pkg = pkgdir::Pkg::temporary(args.instance().pkg());
+    let remote_dir = pkgdir::mksimple(args.path(), &pkg)?;
+    let client = AemClient::new(args.instance())?;
+    fetch_pkg(&client, &remote_dir, &pkg, args.debug(), args.build_wait())?;
+    fsops::cleanup_files(args.ignore_properties(), &remote_dir)?;
+    let local_dir = TempDir::new()?;
+    cp_files_to_pkg(args.path(), &local_dir)?;
+    fsops::cleanup_files(args.ignore_properties(), &local_dir)?;
+    let after_root = args.path().after_root()?;
+    let differs = diff::write_diff(
+        remote_dir.path().join(&after_root),
+        local_dir.path().join(&after_root),
+        &after_root,
+        w,
+    )?;
+    Ok(if differs {
+        ExitCode::from(DIFFERS)
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+pub(crate) fn ls(args: &LsArgs, w: &mut impl Write) -> Result<()> {
+    info!("executing 'ls {}'", args.path().full());
+    let jcr_path = args.path().jcr_path()?;
+    let client = AemClient::new(args.instance())?;
+    let node = jcr::get_node(&client, &jcr_path, args.depth())?;
+    jcr::write_tree(&jcr_path, &node, w)?;
+    Ok(())
+}
+
+pub(crate) fn cat(args: &CatArgs, w: &mut impl Write) -> Result<()> {
+    info!("executing 'cat {}'", args.path().full());
+    let jcr_path = args.path().jcr_path()?;
+    let client = AemClient::new(args.instance())?;
+    match args.format() {
+        CatFormat::Json => {
+            let mut node = jcr::get_node(&client, &jcr_path, 0)?;
+            fsops::cleanup_json_props(&mut node, args.ignore_properties());
+            writeln!(w, "{}", serde_json::to_string_pretty(&node)?)?;
+        }
+        CatFormat::Xml => {
+            let pkg = pkgdir::Pkg::temporary(args.instance().pkg());
+            let tmp_dir = pkgdir::mknode(&jcr_path, &pkg)?;
+            fetch_pkg(&client, &tmp_dir, &pkg, args.debug(), args.build_wait())?;
+            fsops::cleanup_files(args.ignore_properties(), &tmp_dir)?;
+            let node_path = tmp_dir
+                .path()
+                .join(format!("jcr_root{}", platform::platform_path(&jcr_path)));
+            let content_xml = node_path.join(CONTENT_XML);
+            if content_xml.is_file() {
+                write!(w, "{}", read_to_string(content_xml)?)?;
+            } else if node_path.is_file() {
+                write!(w, "{}", String::from_utf8_lossy(&fs::read(node_path)?))?;
+            } else {
+                bail!("node {} was not found in the downloaded package", jcr_path);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Zips the package once (per package settings of the profiles) and installs it to all selected
+/// instances; when there are more of them, they are handled in parallel and a failure of one
+/// doesn't stop the others.
+pub(crate) fn put(args: &PutArgs, w: &mut impl Write) -> Result<ExitCode> {
     info!("executing 'put {}'", args.path().full());
-    let pkg = pkgdir::Pkg::default();
+    let parallel = args.instances().len() > 1;
+    let clients = args
+        .instances()
+        .iter()
+        .map(|instance| {
+            let client = AemClient::new(instance)?;
+            // progress bars of parallel uploads would overwrite each other
+            Ok(if parallel {
+                client.without_progress()
+            } else {
+                client
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    // snapshots are saved before anything is installed, so when one fails all instances are intact
+    for (instance, client) in args.instances().iter().zip(&clients) {
+        if args.snapshot_of(instance) && !args.dry_run() {
+            save_snapshot(client, instance, args, w)?;
+        }
+    }
+    let mut zipped: Vec<(&PkgSettings, pkgdir::Pkg, TempDir)> = Vec::new();
+    for instance in args.instances() {
+        if zipped
+            .iter()
+            .all(|(settings, ..)| *settings != instance.pkg())
+        {
+            let pkg = pkgdir::Pkg::temporary(instance.pkg());
+            let tmp_dir = pkgdir::mksimple(args.path(), &pkg)?;
+            cp_files_to_pkg(args.path(), &tmp_dir)?;
+            pkg::zip_pkg(&tmp_dir)?;
+            zipped.push((instance.pkg(), pkg, tmp_dir));
+        }
+    }
+    let install = |instance: &Instance, client: &AemClient, w: &mut Vec<u8>| {
+        let (_, pkg, tmp_dir) = zipped
+            .iter()
+            .find(|(settings, ..)| *settings == instance.pkg())
+            .expect("package is zipped for each instance");
+        install_zipped(client, tmp_dir, pkg, args, w)
+    };
+    if let ([instance], [client]) = (args.instances().as_slice(), clients.as_slice()) {
+        let mut output = Vec::new();
+        let res = install(instance, client, &mut output);
+        w.write_all(&output)?;
+        res?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    let results: Vec<(Result<()>, Vec<u8>)> = thread::scope(|scope| {
+        let handles: Vec<_> = args
+            .instances()
+            .iter()
+            .zip(&clients)
+            .map(|(instance, client)| {
+                scope.spawn(|| {
+                    let mut output = Vec::new();
+                    let res = install(instance, client, &mut output);
+                    (res, output)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| {
+                // a panic fails only its instance, summaries of the others are still printed
+                handle
+                    .join()
+                    .unwrap_or_else(|panic| (Err(panicked(panic)), Vec::new()))
+            })
+            .collect()
+    });
+    let mut failed = false;
+    for (instance, (res, output)) in args.instances().iter().zip(results) {
+        let name = instance.name();
+        for line in String::from_utf8_lossy(&output).lines() {
+            writeln!(w, "[{}] {}", name, line)?;
+        }
+        match res {
+            Ok(()) => writeln!(w, "[{}] ok", name)?,
+            Err(e) => {
+                debug!("put error on {}: {:?}", name, e);
+                writeln!(w, "[{}] failed: {}", name, e)?;
+                failed = true;
+            }
+        }
+    }
+    Ok(if failed {
+        ExitCode::from(ERROR)
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// Error of the thread which panicked, with the panic message.
+fn panicked(panic: Box<dyn Any + Send>) -> anyhow::Error {
+    let message = panic
+        .downcast_ref::<&str>()
+        .map(ToString::to_string)
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_default();
+    anyhow!("unexpected error: {}", message)
+}
+
+/// Uploads already zipped package to the instance and installs it.
+fn install_zipped(
+    client: &AemClient,
+    tmp_dir: &TempDir,
+    pkg: &pkgdir::Pkg,
+    args: &PutArgs,
+    w: &mut impl Write,
+) -> Result<()> {
+    pkgmgr::upload_pkg(client, tmp_dir)?;
+    install_or_dry_run(client, &pkg.path(), args.dry_run(), w)?;
+    pkgmgr::delete_pkg(client, args.debug(), &pkg.path())?;
+    Ok(())
+}
+
+/// Downloads a package with the remote content of the uploaded path and saves it locally,
+/// so `rollback` can install it back.
+fn save_snapshot(
+    client: &AemClient,
+    instance: &Instance,
+    args: &PutArgs,
+    w: &mut impl Write,
+) -> Result<()> {
+    let jcr_path = args.path().content()?;
+    let pkg = pkgdir::Pkg::snapshot(instance.pkg());
     let tmp_dir = pkgdir::mksimple(args.path(), &pkg)?;
-    let client = AemClient::new(args.instance());
-    cp_files_to_pkg(args.path(), &tmp_dir)?;
+    build_and_download(client, &tmp_dir, &pkg, args.debug(), args.build_wait())?;
+    let snapshot = Snapshot::new(instance, &jcr_path, pkg);
+    let snapshot_dir = snapshot::save(
+        &args.root().join(SNAPSHOTS_DIR),
+        &snapshot,
+        &tmp_dir.path().join("res.zip"),
+        args.snapshots().keep(),
+    )?;
+    writeln!(
+        w,
+        "saved snapshot of {} to {}",
+        jcr_path,
+        snapshot_dir.display()
+    )?;
+    Ok(())
+}
+
+pub(crate) fn rollback(args: &RollbackArgs, w: &mut impl Write) -> Result<()> {
+    info!("executing 'rollback {}'", args.path().full());
+    let jcr_path = args.path().jcr_path()?;
+    let profile = args.instance().name();
+    let snapshot = match snapshot::latest(&args.root().join(SNAPSHOTS_DIR), profile, &jcr_path)? {
+        Some(snapshot) => snapshot,
+        None => bail!("no snapshot of {} for profile '{}'", jcr_path, profile),
+    };
+    let client = AemClient::new(args.instance())?;
+    let tmp_dir = TempDir::new()?;
+    fs::copy(snapshot.pkg_zip(), tmp_dir.path().join("pkg.zip"))?;
+    pkgmgr::upload_pkg(&client, &tmp_dir)?;
+    install_or_dry_run(&client, &snapshot.pkg().path(), args.dry_run(), w)?;
+    pkgmgr::delete_pkg(&client, args.debug(), &snapshot.pkg().path())?;
+    if !args.dry_run() {
+        snapshot.remove()?;
+        writeln!(w, "rolled back {} on {}", jcr_path, args.instance().addr())?;
+    }
+    Ok(())
+}
+
+pub(crate) fn rm(args: &RmArgs, input: &mut impl BufRead, w: &mut impl Write) -> Result<()> {
+    info!("executing 'rm {}'", args.path().full());
+    let jcr_path = args.path().jcr_path()?;
+    if jcr_path.split('/').filter(|s| !s.is_empty()).count() < 2 {
+        bail!("refusing to delete top-level node {}", jcr_path);
+    }
+    let client = AemClient::new(args.instance())?;
+    // fails when the node doesn't exist, so a typo doesn't end up in an installed package
+    jcr::get_node(&client, &jcr_path, 0)?;
+    let addr = args.instance().addr();
+    if args.dry_run() {
+        writeln!(w, "would delete {} on {}", jcr_path, addr)?;
+        return Ok(());
+    }
+    let question = format!("delete {} with all descendants on {}?", jcr_path, addr);
+    if !args.yes() && !confirm(&question, input, w)? {
+        writeln!(w, "aborted")?;
+        return Ok(());
+    }
+    let pkg = pkgdir::Pkg::temporary(args.instance().pkg());
+    let tmp_dir = pkgdir::mkdelete(&jcr_path, &pkg)?;
     pkg::zip_pkg(&tmp_dir)?;
     pkgmgr::upload_pkg(&client, &tmp_dir)?;
-    pkgmgr::install_pkg(&client, &pkg)?;
-    pkgmgr::delete_pkg(&client, args.debug(), &pkg)?;
+    pkgmgr::install_pkg(&client, &pkg.path())?;
+    pkgmgr::delete_pkg(&client, args.debug(), &pkg.path())?;
+    writeln!(w, "deleted {}", jcr_path)?;
     Ok(())
 }
 
+fn confirm(question: &str, input: &mut impl BufRead, w: &mut impl Write) -> Result<bool> {
+    write!(w, "{} [y/N] ", question)?;
+    w.flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
 pub(crate) fn get_bundle(args: &GetBundleArgs) -> Result<()> {
     info!("executing 'get bundle {:?}'", args.bundle());
-    let pkg = pkgdir::Pkg::default();
+    let pkg = pkgdir::Pkg::temporary(args.instance().pkg());
     let tmp_dir = pkgdir::mkbundle(args.bundle(), &pkg)?;
-    let client = AemClient::new(args.instance());
-    pkg::zip_pkg(&tmp_dir)?;
-    pkgmgr::upload_pkg(&client, &tmp_dir)?;
-    pkgmgr::build_pkg(&client, &pkg)?;
-    thread::sleep(Duration::from_millis(100));
-    pkgdir::clean(&tmp_dir)?;
-    pkgmgr::download_pkg(&client, &tmp_dir, &pkg)?;
-    pkgmgr::delete_pkg(&client, args.debug(), &pkg)?;
-    pkg::unzip_pkg(&tmp_dir)?;
+    let client = AemClient::new(args.instance())?;
+    fetch_pkg(&client, &tmp_dir, &pkg, args.debug(), args.build_wait())?;
     fsops::cleanup_files(args.ignore_properties(), &tmp_dir)?;
+    let local_paths: Vec<&String> = args.bundle().paths().iter().map(|p| p.path()).collect();
+    backup::snapshot(
+        &args.root().join(BACKUPS_DIR),
+        &local_paths,
+        args.backups().keep(),
+    )?;
     fsops::mv_bundle_back(&tmp_dir, args.bundle())?;
     Ok(())
 }
 
+/// Adjusts the config in the current directory (or the nearest one) to the newest version,
+/// keeping its comments and formatting.
+pub(crate) fn reinit(opt: &Opt) -> Result<()> {
+    let mut cfg_file = CfgFile::open(edited_cfg(opt)?)?;
+    info!("reinitializing config file {}", cfg_file.path().display());
+    cfg_file.migrate()?;
+    cfg_file.save()
+}
+
+/// File changed by `je config` and `je reinit`: the `--config` file, the nearest `.je` or a new
+/// one in the current directory.
+fn edited_cfg(opt: &Opt) -> Result<PathBuf> {
+    if let Some(config) = &opt.config {
+        return Ok(config.clone());
+    }
+    let cfg_files = CfgFiles::locate(None, &[&env::current_dir()?])?;
+    Ok(cfg_files
+        .project
+        .unwrap_or_else(|| PathBuf::from(CONFIG_FILE)))
+}
+
+pub(crate) fn config(cmd: &ConfigCmd, opt: &Opt, w: &mut impl Write) -> Result<()> {
+    info!("executing 'config {:?}'", cmd);
+    match cmd {
+        ConfigCmd::Check => {
+            let cfg_files = CfgFiles::locate(opt.config.as_deref(), &[&env::current_dir()?])?;
+            let cfg = handle_cfg_load(&cfg_files, opt.profile.as_ref())?;
+            cfg.instances(opt.profile.as_ref())?;
+            let files: Vec<String> = [&cfg_files.global, &cfg_files.project]
+                .into_iter()
+                .flatten()
+                .map(|file| file.display().to_string())
+                .collect();
+            if files.is_empty() {
+                writeln!(w, "no config file found, the default config is used")?;
+            } else {
+                writeln!(w, "config is correct: {}", files.join(", "))?;
+            }
+        }
+        ConfigCmd::Show => {
+            let cfg_files = CfgFiles::locate(opt.config.as_deref(), &[&env::current_dir()?])?;
+            // credentials are not resolved, so secrets kept outside of the config aren't shown
+            let mut cfg = load_merged_cfg(&cfg_files)?;
+            cfg.profiles = cfg.profiles.iter().map(Instance::redacted).collect();
+            write!(w, "{}", toml::to_string(&cfg)?)?;
+        }
+        ConfigCmd::Profile(cmd) => {
+            edit_cfg(opt, w, |cfg_file| match cmd {
+                ProfileCmd::Add {
+                    name, addr, user, ..
+                } => cfg_file.add_profile(name, addr, user.as_deref()),
+                ProfileCmd::Remove { name } => cfg_file.remove_profile(name),
+                ProfileCmd::SetDefault { name } => cfg_file.set_default_profile(name),
+            })?;
+            if let ProfileCmd::Add {
+                name,
+                pass: Some(pass),
+                ..
+            } = cmd
+            {
+                let store = store_password(name, pass)?;
+                writeln!(w, "saved password of '{}' to {}", name, store.display())?;
+            }
+        }
+        ConfigCmd::Bundle(cmd) => edit_cfg(opt, w, |cfg_file| match cmd {
+            BundleCmd::Add { name, paths } => cfg_file.add_bundle(name, paths),
+            BundleCmd::Remove { name } => cfg_file.remove_bundle(name),
+            BundleCmd::AddPath { name, path } => cfg_file.add_bundle_path(name, path),
+        })?,
+        ConfigCmd::Ignore(IgnoreCmd::Add { ignore_type, value }) => edit_cfg(opt, w, |cfg_file| {
+            cfg_file.add_ignore_property(ignore_type, value)
+        })?,
+    }
+    Ok(())
+}
+
+/// Applies the `change` to the edited config and saves it, when the changed config is correct.
+fn edit_cfg(
+    opt: &Opt,
+    w: &mut impl Write,
+    change: impl FnOnce(&mut CfgFile) -> Result<()>,
+) -> Result<()> {
+    let mut cfg_file = CfgFile::open(edited_cfg(opt)?)?;
+    change(&mut cfg_file)?;
+    cfg_file.save()?;
+    writeln!(w, "updated {}", cfg_file.path().display())?;
+    Ok(())
+}
+
+pub(crate) fn undo(root: &OsPath, w: &mut impl Write) -> Result<()> {
+    info!("executing 'undo'");
+    match backup::restore_latest(&root.join(BACKUPS_DIR))? {
+        Some(restored) => {
+            for path in restored {
+                writeln!(w, "restored {}", path.display())?;
+            }
+        }
+        None => writeln!(w, "nothing to undo")?,
+    }
+    Ok(())
+}
+
+pub(crate) fn put_bundle(args: &PutBundleArgs, w: &mut impl Write) -> Result<()> {
+    info!("executing 'put bundle {:?}'", args.bundle());
+    let client = AemClient::new(args.instance())?;
+    install_bundle(&client, args.bundle(), args.debug(), args.dry_run(), w)?;
+    Ok(())
+}
+
+pub(crate) fn pkg(cmd: &PkgCmd, args: &PkgArgs, w: &mut impl Write) -> Result<()> {
+    info!("executing 'pkg {:?}'", cmd);
+    let client = AemClient::new(args.instance())?;
+    match cmd {
+        PkgCmd::List { group } => {
+            let pkgs: Vec<pkgmgr::PkgInfo> = pkgmgr::list_pkgs(&client)?
+                .into_iter()
+                .filter(|info| group.as_ref().is_none_or(|g| info.group() == g))
+                .collect();
+            write_pkgs(&pkgs, w)?;
+        }
+        PkgCmd::Download { package, output } => {
+            let info = pkgmgr::find_pkg(&client, package)?;
+            let pkg_path = info.rel_path();
+            let output = match output {
+                Some(output) => PathBuf::from(output),
+                None => PathBuf::from(OsPath::new(pkg_path).file_name().unwrap_or_default()),
+            };
+            pkgmgr::download_to(&client, pkg_path, &output)?;
+            writeln!(w, "downloaded {} to {}", pkg_path, output.display())?;
+        }
+        PkgCmd::Upload {
+            file,
+            install,
+            force,
+        } => {
+            let path = pkgmgr::upload_file(&client, file, *force)?;
+            if path.is_empty() {
+                bail!("instance didn't return the path of uploaded {}", file);
+            }
+            writeln!(w, "uploaded {} to {}", file, path)?;
+            if *install {
+                let info = pkgmgr::find_pkg(&client, &path)?;
+                install_or_dry_run(&client, info.rel_path(), args.dry_run(), w)?;
+                writeln!(w, "installed {}", info.rel_path())?;
+            }
+        }
+        PkgCmd::Install { package } => {
+            let info = pkgmgr::find_pkg(&client, package)?;
+            install_or_dry_run(&client, info.rel_path(), args.dry_run(), w)?;
+            if !args.dry_run() {
+                writeln!(w, "installed {}", info.rel_path())?;
+            }
+        }
+        PkgCmd::Uninstall { package } => {
+            let info = pkgmgr::find_pkg(&client, package)?;
+            pkgmgr::uninstall_pkg(&client, info.rel_path())?;
+            writeln!(w, "uninstalled {}", info.rel_path())?;
+        }
+        PkgCmd::Delete { package } => {
+            let info = pkgmgr::find_pkg(&client, package)?;
+            pkgmgr::delete_pkg(&client, false, info.rel_path())?;
+            writeln!(w, "deleted {}", info.rel_path())?;
+        }
+        PkgCmd::Prune { older_than } => prune(&client, args, *older_than, w)?,
+    }
+    Ok(())
+}
+
+/// Deletes temporary and snapshot packages created by `je` in the profile's package group
+/// earlier than `older_than` ago; recent ones may still be used by other runs.
+fn prune(client: &AemClient, args: &PkgArgs, older_than: Age, w: &mut impl Write) -> Result<()> {
+    let settings = args.instance().pkg();
+    let deadline = SystemTime::now()
+        .duration_since(UNIX_EPOCH)?
+        .saturating_sub(older_than.0)
+        .as_secs();
+    let stale = stale_pkgs(pkgmgr::list_pkgs(client)?, settings, deadline);
+    if stale.is_empty() {
+        writeln!(w, "nothing to prune")?;
+    }
+    for info in stale {
+        if args.dry_run() {
+            writeln!(w, "would delete {}", info.rel_path())?;
+        } else {
+            pkgmgr::delete_pkg(client, false, info.rel_path())?;
+            writeln!(w, "deleted {}", info.rel_path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Packages created by `je` with given settings before `deadline` (in seconds since epoch).
+fn stale_pkgs(
+    pkgs: Vec<pkgmgr::PkgInfo>,
+    settings: &PkgSettings,
+    deadline: u64,
+) -> Vec<pkgmgr::PkgInfo> {
+    pkgs.into_iter()
+        .filter(|info| info.group() == settings.group())
+        .filter(|info| {
+            pkgdir::Pkg::created_by_je(settings, info.name(), info.version())
+                .is_some_and(|created| created < deadline)
+        })
+        .collect()
+}
+
+/// Writes packages as a table with aligned columns.
+fn write_pkgs(pkgs: &[pkgmgr::PkgInfo], w: &mut impl Write) -> Result<()> {
+    let rows: Vec<[&str; 4]> = pkgs
+        .iter()
+        .map(|info| {
+            let status = if info.is_installed() {
+                "installed"
+            } else {
+                "not installed"
+            };
+            [
+                info.group().as_str(),
+                info.name().as_str(),
+                info.version().as_str(),
+                status,
+            ]
+        })
+        .collect();
+    let header = ["GROUP", "NAME", "VERSION", "STATUS"];
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        writeln!(w, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+pub(crate) fn watch(args: &WatchArgs, w: &mut impl Write) -> Result<()> {
+    info!("executing 'watch {}'", args.path().full());
+    let client = AemClient::new(args.instance())?;
+    let (tx, rx) = channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(OsPath::new(&args.path().full()), RecursiveMode::Recursive)?;
+    writeln!(w, "watching {} for changes", args.path().full())?;
+    while let Some(changed) = watch::next_changes(&rx, args.debounce()) {
+        let paths: Vec<String> = watch::paths_to_sync(changed)
+            .iter()
+            .map(Path::full)
+            .collect();
+        if paths.is_empty() {
+            continue;
+        }
+        let listed = paths.join(", ");
+        let bundle = Bundle::new("watch".into(), paths);
+        match install_bundle(&client, &bundle, args.debug(), false, w) {
+            Ok(()) => writeln!(w, "synced {listed}")?,
+            Err(e) => {
+                debug!("sync error: {:?}", e);
+                writeln!(w, "failed to sync {listed}: {e}")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Uploads and installs single package containing all paths of the `bundle`.
+fn install_bundle(
+    client: &AemClient,
+    bundle: &Bundle,
+    debug: bool,
+    dry_run: bool,
+    w: &mut impl Write,
+) -> Result<()> {
+    let pkg = pkgdir::Pkg::temporary(client.instance().pkg());
+    let tmp_dir = pkgdir::mkbundle(bundle, &pkg)?;
+    for bundle_path in bundle.paths() {
+        cp_files_to_pkg(&Path::new(bundle_path.path()), &tmp_dir)?;
+    }
+    pkg::zip_pkg(&tmp_dir)?;
+    pkgmgr::upload_pkg(client, &tmp_dir)?;
+    // the uploaded package is deleted also when it can't be installed
+    let res = install_or_dry_run(client, &pkg.path(), dry_run, w);
+    let deleted = pkgmgr::delete_pkg(client, debug, &pkg.path());
+    res.and(deleted)
+}
+
+/// Installs uploaded package or, in dry-run mode, only prints changes the installation would make.
+fn install_or_dry_run(
+    client: &AemClient,
+    pkg_path: &str,
+    dry_run: bool,
+    w: &mut impl Write,
+) -> Result<()> {
+    if dry_run {
+        write_changes(&pkgmgr::dry_run_pkg(client, pkg_path)?, w)
+    } else {
+        pkgmgr::install_pkg(client, pkg_path)
+    }
+}
+
+fn write_changes(changes: &[String], w: &mut impl Write) -> Result<()> {
+    if changes.is_empty() {
+        writeln!(w, "no changes")?;
+    }
+    for change in changes {
+        writeln!(w, "{}", change)?;
+    }
+    Ok(())
+}
+
+/// Builds the package (with filter already written to `tmp_dir`) on the instance,
+/// then downloads and extracts it to `tmp_dir`.
+fn fetch_pkg(
+    client: &AemClient,
+    tmp_dir: &TempDir,
+    pkg: &pkgdir::Pkg,
+    debug: bool,
+    build_wait: &BuildWait,
+) -> Result<()> {
+    build_and_download(client, tmp_dir, pkg, debug, build_wait)?;
+    pkg::unzip_pkg(tmp_dir)?;
+    Ok(())
+}
+
+/// Builds the package (with filter already written to `tmp_dir`) on the instance,
+/// then downloads it to `res.zip` in `tmp_dir`.
+/// Builds the package on the instance and downloads it; the uploaded package is deleted also
+/// when it can't be built or downloaded, then the original error is returned.
+fn build_and_download(
+    client: &impl Client,
+    tmp_dir: &TempDir,
+    pkg: &pkgdir::Pkg,
+    debug: bool,
+    build_wait: &BuildWait,
+) -> Result<()> {
+    pkg::zip_pkg(tmp_dir)?;
+    pkgmgr::upload_pkg(client, tmp_dir)?;
+    let res = pkgmgr::build_pkg(client, pkg)
+        .and_then(|_| pkgmgr::wait_for_build(client, pkg, build_wait))
+        .and_then(|_| pkgdir::clean(tmp_dir))
+        .and_then(|_| pkgmgr::download_pkg(client, tmp_dir, pkg));
+    let deleted = pkgmgr::delete_pkg(client, debug, &pkg.path());
+    res.and(deleted)
+}
+
 fn cp_files_to_pkg(path: &Path, tmp_dir: &TempDir) -> Result<()> {
-    let dst_path = dst_path(path, tmp_dir);
+    let dst_path = dst_path(path, tmp_dir)?;
     info!(
         "copying files from {} to {}",
         path.full(),
         dst_path.display()
     );
-    fs::create_dir_all(tmp_dir.path().join(path.parent_from_root()))?;
+    fs::create_dir_all(tmp_dir.path().join(path.parent_from_root()?))?;
     if path.is_dir() {
         debug!("{} is a dir", path.full());
         dir::copy(path.full(), dst_path, &DirOpts::new())?;
@@ -192,19 +1103,19 @@ fn cp_files_to_pkg(path: &Path, tmp_dir: &TempDir) -> Result<()> {
     Ok(())
 }
 
-fn dst_path(path: &Path, tmp_dir: &TempDir) -> PathBuf {
+fn dst_path(path: &Path, tmp_dir: &TempDir) -> Result<PathBuf> {
     let result = if path.is_dir() {
-        tmp_dir.path().join(path.parent_from_root())
+        tmp_dir.path().join(path.parent_from_root()?)
     } else {
-        tmp_dir.path().join(path.after_root())
+        tmp_dir.path().join(path.after_root()?)
     };
-    result
+    Ok(result)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::testutils::TestConfig;
+    use crate::testutils::{ClientSpy, TestConfig};
     use anyhow::Result;
     use std::env;
     use std::fs::{create_dir_all, read_to_string, File};
@@ -305,6 +1216,84 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_config_check() -> Result<()> {
+        // given
+        let tmp_dir = TempDir::new()?;
+        let config = tmp_dir.path().join("config.toml");
+        std::fs::write(
+            &config,
+            r#"version = "0.3.0"
+
+               [[profile]]
+               name = "author"
+               addr = "http://localhost:4502"
+               user = "user1"
+               pass = "pass1"
+            "#,
+        )?;
+        let opt = |profile: &str| Opt {
+            cmd: Cmd::Config(ConfigCmd::Check),
+            profile: Some(profile.into()),
+            config: Some(config.clone()),
+            ..Opt::default()
+        };
+        let mut writer = Vec::new();
+
+        // when
+        handle(&opt("author"), &mut writer)?;
+        let res = handle(&opt("autor"), &mut Vec::new());
+
+        // then
+        assert!(String::from_utf8_lossy(&writer).starts_with("config is correct: "));
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "unknown profile or group 'autor', available profiles: author; no groups are defined"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_show_redacts_secrets() -> Result<()> {
+        // given
+        let tmp_dir = TempDir::new()?;
+        let config = tmp_dir.path().join("config.toml");
+        std::fs::write(
+            &config,
+            r#"version = "0.3.0"
+
+               [[profile]]
+               name = "author"
+               addr = "http://localhost:4502"
+               user = "admin"
+               pass = "secret"
+
+               [[profile]]
+               name = "cloud"
+               addr = "https://author.adobeaemcloud.com"
+               pass = "env:AEM_PASS"
+               auth = { type = "bearer", token = "secret-token" }
+            "#,
+        )?;
+        let opt = Opt {
+            cmd: Cmd::Config(ConfigCmd::Show),
+            config: Some(config),
+            ..Opt::default()
+        };
+        let mut writer = Vec::new();
+
+        // when
+        handle(&opt, &mut writer)?;
+
+        // then
+        let output = String::from_utf8_lossy(&writer);
+        assert!(!output.contains("secret"));
+        assert!(output.contains(r#"pass = "<redacted>""#));
+        assert!(output.contains(r#"pass = "env:AEM_PASS""#));
+        assert!(output.contains(r#"token = "<redacted>""#));
+        Ok(())
+    }
+
     #[test]
     fn test_init() -> Result<()> {
         // given
@@ -378,7 +1367,7 @@ pass = "admin"
         let path = Path::new(filepath);
 
         // when
-        let dst = dst_path(&path, &tmp_dir);
+        let dst = dst_path(&path, &tmp_dir)?;
 
         // then
         assert_eq!(dst, expected_path);
@@ -396,10 +1385,157 @@ pass = "admin"
         let expected_path = tmp_dir.path().join("jcr_root/content/project/en_gb/home");
 
         // when
-        let dst = dst_path(&path, &tmp_dir);
+        let dst = dst_path(&path, &tmp_dir)?;
 
         // then
         assert_eq!(dst, expected_path);
         Ok(())
     }
+
+    #[test]
+    fn test_rm_refuses_top_level_nodes() -> Result<()> {
+        // given
+        let cfg = Cfg::default();
+        let opt = Opt::default();
+        let mut writer = Vec::new();
+
+        for path in ["/", "/content", "/project/jcr_root/apps"] {
+            // when
+            let res = rm(
+                &RmArgs::new(path, true, &cfg, &opt)?,
+                &mut "y\n".as_bytes(),
+                &mut writer,
+            );
+
+            // then
+            assert!(res
+                .unwrap_err()
+                .to_string()
+                .starts_with("refusing to delete top-level node"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_pkgs() -> Result<()> {
+        // given
+        let pkgs: Vec<pkgmgr::PkgInfo> = serde_json::from_str(
+            r#"[
+                {"group":"my_packages","name":"site","version":"1.0.12","lastUnpacked":1},
+                {"group":"adobe/consulting","name":"acs-aem-commons","version":"5.0"}
+            ]"#,
+        )?;
+        let mut writer = Vec::new();
+
+        // when
+        write_pkgs(&pkgs, &mut writer)?;
+
+        // then
+        assert_eq!(
+            String::from_utf8(writer)?,
+            r#"GROUP             NAME             VERSION  STATUS
+my_packages       site             1.0.12   installed
+adobe/consulting  acs-aem-commons  5.0      not installed
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_panicked() {
+        // given
+        let panic = thread::spawn(|| panic!("instance {} broke", "qa"))
+            .join()
+            .unwrap_err();
+
+        // when
+        let err = panicked(panic);
+
+        // then
+        assert_eq!(err.to_string(), "unexpected error: instance qa broke");
+    }
+
+    #[test]
+    fn test_stale_pkgs() -> Result<()> {
+        // given
+        let pkgs: Vec<pkgmgr::PkgInfo> = serde_json::from_str(
+            r#"[
+                {"group":"my_packages","name":"je-pkg","version":"1700000000-0a1b2c"},
+                {"group":"my_packages","name":"je-snapshot","version":"1700000000-0a1b2c"},
+                {"group":"my_packages","name":"je-pkg","version":"1700000000"},
+                {"group":"my_packages","name":"je-pkg","version":"1800000000-0a1b2c"},
+                {"group":"my_packages","name":"jenkins-build","version":"42"},
+                {"group":"other","name":"je-pkg","version":"1700000000-0a1b2c"}
+            ]"#,
+        )?;
+        let settings: PkgSettings = toml::from_str(r#"group = "my_packages""#)?;
+
+        // when
+        let stale = stale_pkgs(pkgs, &settings, 1_750_000_000);
+
+        // then
+        let pids: Vec<String> = stale.iter().map(pkgmgr::PkgInfo::pid).collect();
+        assert_eq!(
+            pids,
+            vec![
+                "my_packages:je-pkg:1700000000-0a1b2c",
+                "my_packages:je-snapshot:1700000000-0a1b2c",
+                "my_packages:je-pkg:1700000000"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_confirm() -> Result<()> {
+        let test_cases = [
+            ("y\n", true),
+            ("yes\n", true),
+            ("n\n", false),
+            ("\n", false),
+            ("", false),
+        ];
+
+        for (answer, expected) in test_cases {
+            // given
+            let mut writer = Vec::new();
+
+            // when
+            let confirmed = confirm("delete?", &mut answer.as_bytes(), &mut writer)?;
+
+            // then
+            assert_eq!(confirmed, expected, "answer: {answer:?}");
+            assert_eq!(String::from_utf8_lossy(&writer), "delete? [y/N] ");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_and_download_deletes_pkg_after_build_timeout() -> Result<()> {
+        // given
+        let success = r#"{"success":true,"msg":"Done"}"#;
+        let spy = ClientSpy::with_responses(&[
+            (200, success),
+            (200, success),
+            (200, r#"{"results":[{"buildCount":0}],"total":1}"#),
+            (200, success),
+        ]);
+        let tmp_dir = TempDir::new()?;
+        let pkg = pkgdir::Pkg::default();
+
+        // when
+        let res = build_and_download(&spy, &tmp_dir, &pkg, false, &BuildWait::new(0, 1, 1));
+
+        // then
+        assert!(res.unwrap_err().to_string().contains("was not built"));
+        assert_eq!(
+            spy.post_req(),
+            format!(
+                "/crx/packmgr/service/.json/etc/packages/{}?cmd=delete",
+                pkg.path()
+            )
+        );
+        assert_eq!(spy.remaining_responses(), 0);
+        Ok(())
+    }
 }
@@ -0,0 +1,311 @@
+use anyhow::Result;
+use log::debug;
+use similar::TextDiff;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+const MISSING: &str = "/dev/null";
+
+/// Writes unified diff between `remote` and `local` (file or directory) to `w`. Paths in
+/// the headers are prefixed with `label`. Returns `true` if any differences were found.
+pub(crate) fn write_diff<A: AsRef<Path>, B: AsRef<Path>, S: AsRef<str>>(
+    remote: A,
+    local: B,
+    label: S,
+    w: &mut impl Write,
+) -> Result<bool> {
+    let (remote, local, label) = (remote.as_ref(), local.as_ref(), label.as_ref());
+    if remote.is_file() || local.is_file() {
+        return diff_file(remote, local, label, w);
+    }
+    let mut differs = false;
+    for rel_path in files_under(remote).union(&files_under(local)) {
+        let label = format!("{}/{}", label, rel_path.display());
+        differs |= diff_file(remote.join(rel_path), local.join(rel_path), label, w)?;
+    }
+    Ok(differs)
+}
+
+/// Lists changes which replacing `local` (file or directory) with `remote` would make, one line
+/// per local file: `A` (created), `U` (overwritten with different content) or `D` (deleted).
+pub(crate) fn changed_files<A: AsRef<Path>, B: AsRef<Path>>(
+    remote: A,
+    local: B,
+) -> Result<Vec<String>> {
+    let (remote, local) = (remote.as_ref(), local.as_ref());
+    let rel_paths = if remote.is_file() || local.is_file() {
+        BTreeSet::from([PathBuf::new()])
+    } else {
+        files_under(remote)
+            .union(&files_under(local))
+            .cloned()
+            .collect()
+    };
+    // joining an empty path would add a trailing separator
+    let join = |root: &Path, rel_path: &Path| {
+        if rel_path.as_os_str().is_empty() {
+            root.to_path_buf()
+        } else {
+            root.join(rel_path)
+        }
+    };
+    let mut changes = Vec::new();
+    for rel_path in rel_paths {
+        let (remote_file, local_file) = (join(remote, &rel_path), join(local, &rel_path));
+        let change = match (read_if_exists(&remote_file)?, read_if_exists(&local_file)?) {
+            (Some(_), None) => "A",
+            (None, Some(_)) => "D",
+            (Some(r), Some(l)) if r != l => "U",
+            _ => continue,
+        };
+        changes.push(format!("{} {}", change, local_file.display()));
+    }
+    Ok(changes)
+}
+
+fn files_under(root: &Path) -> BTreeSet<PathBuf> {
+    WalkDir::new(root)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| e.path().is_file())
+        .filter_map(|e| e.path().strip_prefix(root).map(Path::to_path_buf).ok())
+        .collect()
+}
+
+fn diff_file<A: AsRef<Path>, B: AsRef<Path>, S: AsRef<str>>(
+    remote: A,
+    local: B,
+    label: S,
+    w: &mut impl Write,
+) -> Result<bool> {
+    let (remote, local, label) = (remote.as_ref(), local.as_ref(), label.as_ref());
+    debug!("comparing {} with {}", remote.display(), local.display());
+    let remote_content = read_if_exists(remote)?;
+    let local_content = read_if_exists(local)?;
+    if remote_content == local_content {
+        return Ok(false);
+    }
+    let old_header = header(&remote_content, "remote", label);
+    let new_header = header(&local_content, "local", label);
+    match (as_text(&remote_content), as_text(&local_content)) {
+        (Some(old), Some(new)) => write!(
+            w,
+            "{}",
+            TextDiff::from_lines(old, new)
+                .unified_diff()
+                .header(&old_header, &new_header)
+        )?,
+        _ => writeln!(w, "Binary files {old_header} and {new_header} differ")?,
+    }
+    Ok(true)
+}
+
+fn read_if_exists(path: &Path) -> Result<Option<Vec<u8>>> {
+    Ok(if path.is_file() {
+        Some(fs::read(path)?)
+    } else {
+        None
+    })
+}
+
+fn header(content: &Option<Vec<u8>>, side: &str, label: &str) -> String {
+    match content {
+        Some(_) => format!("{side}/{label}"),
+        None => MISSING.into(),
+    }
+}
+
+fn as_text(content: &Option<Vec<u8>>) -> Option<&str> {
+    match content {
+        Some(bytes) => std::str::from_utf8(bytes).ok(),
+        None => Some(""),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anyhow::Result;
+    use std::fs::{create_dir_all, File};
+    use tempfile::TempDir;
+
+    fn write_file<A: AsRef<Path>>(path: A, content: &str) -> Result<()> {
+        if let Some(parent) = path.as_ref().parent() {
+            create_dir_all(parent)?;
+        }
+        File::create(path)?.write_all(content.as_bytes())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_diff_when_files_are_the_same() -> Result<()> {
+        // given
+        let remote = TempDir::new()?;
+        let local = TempDir::new()?;
+        write_file(remote.path().join("home/.content.xml"), "same\n")?;
+        write_file(local.path().join("home/.content.xml"), "same\n")?;
+        let mut out = Vec::new();
+
+        // when
+        let differs = write_diff(&remote, &local, "jcr_root/content", &mut out)?;
+
+        // then
+        assert!(!differs);
+        assert!(out.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_diff_when_file_changed() -> Result<()> {
+        // given
+        let remote = TempDir::new()?;
+        let local = TempDir::new()?;
+        write_file(remote.path().join(".content.xml"), "first\nsecond\n")?;
+        write_file(local.path().join(".content.xml"), "first\nchanged\n")?;
+        let mut out = Vec::new();
+
+        // when
+        let differs = write_diff(&remote, &local, "jcr_root/content", &mut out)?;
+
+        // then
+        assert!(differs);
+        assert_eq!(
+            String::from_utf8(out)?,
+            r#"--- remote/jcr_root/content/.content.xml
++++ local/jcr_root/content/.content.xml
+@@ -1,2 +1,2 @@
+ first
+-second
++changed
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_diff_when_file_exists_only_on_one_side() -> Result<()> {
+        // given
+        let remote = TempDir::new()?;
+        let local = TempDir::new()?;
+        write_file(remote.path().join("removed.txt"), "old\n")?;
+        write_file(local.path().join("added.txt"), "new\n")?;
+        let mut out = Vec::new();
+
+        // when
+        let differs = write_diff(&remote, &local, "jcr_root", &mut out)?;
+
+        // then
+        assert!(differs);
+        assert_eq!(
+            String::from_utf8(out)?,
+            r#"--- /dev/null
++++ local/jcr_root/added.txt
+@@ -0,0 +1 @@
++new
+--- remote/jcr_root/removed.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-old
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_diff_with_single_file() -> Result<()> {
+        // given
+        let remote = TempDir::new()?;
+        let local = TempDir::new()?;
+        write_file(remote.path().join("file.txt"), "a\n")?;
+        write_file(local.path().join("file.txt"), "b\n")?;
+        let mut out = Vec::new();
+
+        // when
+        let differs = write_diff(
+            remote.path().join("file.txt"),
+            local.path().join("file.txt"),
+            "jcr_root/file.txt",
+            &mut out,
+        )?;
+
+        // then
+        assert!(differs);
+        assert!(String::from_utf8(out)?
+            .starts_with("--- remote/jcr_root/file.txt\n+++ local/jcr_root/file.txt\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_diff_with_binary_files() -> Result<()> {
+        // given
+        let remote = TempDir::new()?;
+        let local = TempDir::new()?;
+        File::create(remote.path().join("image.png"))?.write_all(&[0xff, 0x00])?;
+        File::create(local.path().join("image.png"))?.write_all(&[0xff, 0x01])?;
+        let mut out = Vec::new();
+
+        // when
+        let differs = write_diff(&remote, &local, "jcr_root", &mut out)?;
+
+        // then
+        assert!(differs);
+        assert_eq!(
+            String::from_utf8(out)?,
+            "Binary files remote/jcr_root/image.png and local/jcr_root/image.png differ\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_changed_files() -> Result<()> {
+        // given
+        let remote = TempDir::new()?;
+        let local = TempDir::new()?;
+        write_file(remote.path().join("same.txt"), "same")?;
+        write_file(local.path().join("same.txt"), "same")?;
+        write_file(remote.path().join("a/changed.txt"), "new")?;
+        write_file(local.path().join("a/changed.txt"), "old")?;
+        write_file(remote.path().join("added.txt"), "added")?;
+        write_file(local.path().join("deleted.txt"), "deleted")?;
+
+        // when
+        let changes = changed_files(remote.path(), local.path())?;
+
+        // then
+        let local = local.path().display();
+        assert_eq!(
+            changes,
+            vec![
+                format!("U {}/a/changed.txt", local),
+                format!("A {}/added.txt", local),
+                format!("D {}/deleted.txt", local),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_changed_files_with_single_file() -> Result<()> {
+        // given
+        let remote = TempDir::new()?;
+        let local = TempDir::new()?;
+        write_file(remote.path().join("file.txt"), "new")?;
+        write_file(local.path().join("file.txt"), "old")?;
+
+        // when
+        let changes = changed_files(
+            remote.path().join("file.txt"),
+            local.path().join("file.txt"),
+        )?;
+
+        // then
+        assert_eq!(
+            changes,
+            vec![format!("U {}", local.path().join("file.txt").display())]
+        );
+        Ok(())
+    }
+}
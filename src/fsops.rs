@@ -1,16 +1,17 @@
-use crate::cfg::{Bundle, IgnoreProp, IgnoreType};
+use crate::cfg::{Bundle, IgnoreProp, IgnoreType, PathFilter};
 use crate::path::Path;
-use anyhow::{Context, Result};
+use crate::platform::{self, CONTENT_XML};
+use anyhow::{bail, Context, Result};
 use fs_extra::{dir, file};
 use log::{debug, info, warn};
 use regex::Regex;
-use std::fs::{self, File, OpenOptions};
-use std::io::prelude::*;
-use std::io::BufReader;
+use serde_json::Value;
+use std::fs;
 use std::path::Path as OsPath;
 use std::path::PathBuf;
 use tempfile::TempDir;
 use walkdir::{DirEntry, WalkDir};
+use xmlparser::{ElementEnd, Token, Tokenizer};
 
 struct Entry {
     path: PathBuf,
@@ -19,7 +20,7 @@ struct Entry {
 
 impl Entry {
     fn is_xml_file(&self) -> bool {
-        self.is_file() && self.is_content_xml()
+        self.is_file() && (self.is_content_xml() || platform::is_docview(self.path()))
     }
 
     fn is_file(&self) -> bool {
@@ -33,7 +34,7 @@ impl Entry {
     }
 
     fn is_content_xml(&self) -> bool {
-        let is_xml = self.path().ends_with(".content.xml");
+        let is_xml = self.path().file_name() == Some(CONTENT_XML.as_ref());
         debug!(
             "{} {} xml file",
             self.path().display(),
@@ -70,27 +71,31 @@ impl From<DirEntry> for Entry {
 
 pub(crate) fn cleanup_files(ignore_properties: &[IgnoreProp], tmp_dir: &TempDir) -> Result<()> {
     info!("cleaning files from unwanted properties");
+    let matchers = prop_matchers(ignore_properties);
+    if matchers.is_empty() {
+        return Ok(());
+    }
     for entry in WalkDir::new(tmp_dir.path().join("jcr_root"))
         .into_iter()
         .filter_map(to_entry)
         .filter(Entry::is_xml_file)
     {
         debug!("cleaning file {}", entry.path().display());
-        let mut file = File::open(entry.path())?;
-        let reader = BufReader::new(&mut file);
-        let lines: Vec<_> = reader
-            .lines()
-            .map(|l| l.expect("could not read line"))
-            .filter_map(|l| allowed_prop(l, ignore_properties))
-            .collect();
-
-        let mut file = OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(entry.path())?;
-        debug!("writing cleaned lines back to file");
-        for line in lines {
-            file.write_all(line.as_bytes())?;
+        let content = fs::read_to_string(entry.path())?;
+        let cleaned = match strip_props(&content, &matchers) {
+            Ok(cleaned) => cleaned,
+            Err(e) => {
+                warn!(
+                    "{} is not a correct xml file, skipping: '{}'",
+                    entry.path().display(),
+                    e
+                );
+                continue;
+            }
+        };
+        if cleaned != content {
+            debug!("writing cleaned content back to file");
+            fs::write(entry.path(), cleaned)?;
         }
     }
 
@@ -101,61 +106,180 @@ fn to_entry(result: std::result::Result<DirEntry, walkdir::Error>) -> Option<Ent
     result.ok().map(Entry::from)
 }
 
-fn allowed_prop<S: Into<String>>(line: S, ignore_properties: &[IgnoreProp]) -> Option<String> {
-    let line = line.into();
-    let mut result = true;
-    for ignore_prop in ignore_properties {
-        match ignore_prop.ignore_type {
-            IgnoreType::Contains => {
-                debug!("checking if '{}' contains '{}'", line, ignore_prop.value);
-                if line.contains(&ignore_prop.value) {
-                    debug!("line '{}' contains not allowed property, removing", line);
-                    result = false;
-                    break;
-                }
-            }
-            IgnoreType::Regex => {
-                debug!(
-                    "checking if '{}' matches regex '{}'",
-                    line, ignore_prop.value
-                );
-                let regex = Regex::new(&ignore_prop.value);
-                if let Err(e) = regex {
+enum PropMatcher<'a> {
+    Contains(&'a str),
+    Regex(Regex),
+    Name(&'a str),
+}
+
+impl PropMatcher<'_> {
+    /// Checks the property with its qualified `name` and its text as written in the file
+    /// (`name="value"`).
+    fn matches(&self, name: &str, prop: &str) -> bool {
+        match self {
+            PropMatcher::Contains(value) => prop.contains(value),
+            PropMatcher::Regex(regex) => regex.is_match(prop),
+            PropMatcher::Name(value) => name == *value,
+        }
+    }
+}
+
+fn prop_matchers(ignore_properties: &[IgnoreProp]) -> Vec<PropMatcher<'_>> {
+    ignore_properties
+        .iter()
+        .filter_map(|ignore_prop| match ignore_prop.ignore_type {
+            IgnoreType::Contains => Some(PropMatcher::Contains(&ignore_prop.value)),
+            IgnoreType::Name => Some(PropMatcher::Name(&ignore_prop.value)),
+            IgnoreType::Regex => match Regex::new(&ignore_prop.value) {
+                Ok(regex) => Some(PropMatcher::Regex(regex)),
+                Err(e) => {
                     warn!(
                         "regex '{}' is incorrect, skipping: '{}'",
                         ignore_prop.value, e
                     );
-                    continue;
+                    None
                 }
-                // can unwrap because it's checked earlier
-                let regex = regex.unwrap();
-                if regex.is_match(&line) {
-                    debug!(
-                        "line '{}' matches regex '{}', removing",
-                        line, ignore_prop.value
-                    );
-                    result = false;
-                    break;
+            },
+        })
+        .collect()
+}
+
+/// Removes matching properties (attributes) together with the whitespace preceding them, so the
+/// rest of the document, including FileVault formatting, stays untouched. Namespace declarations
+/// are never removed.
+fn strip_props(xml: &str, matchers: &[PropMatcher]) -> Result<String> {
+    let mut result = String::with_capacity(xml.len());
+    let mut copied_to = 0;
+    let mut prev_end = 0;
+    // tokenizer doesn't check the structure, so names of open elements are tracked here
+    let mut open_elements = Vec::new();
+    for token in Tokenizer::from(xml) {
+        match token? {
+            Token::ElementStart { span, .. } => {
+                open_elements.push(&span.as_str()[1..]);
+                prev_end = span.end();
+            }
+            Token::ElementEnd { end, span } => match end {
+                ElementEnd::Open => {}
+                ElementEnd::Empty => {
+                    open_elements.pop();
                 }
+                ElementEnd::Close(..) => {
+                    let name = span.as_str().trim_start_matches("</").trim_end_matches('>');
+                    if open_elements.pop() != Some(name.trim_end()) {
+                        bail!("unexpected closing tag '{}'", span.as_str());
+                    }
+                }
+            },
+            Token::Attribute {
+                prefix,
+                local,
+                span,
+                ..
+            } => {
+                let is_namespace =
+                    prefix.as_str() == "xmlns" || (prefix.is_empty() && local.as_str() == "xmlns");
+                let name = if prefix.is_empty() {
+                    local.to_string()
+                } else {
+                    format!("{}:{}", prefix, local)
+                };
+                if !is_namespace && matchers.iter().any(|m| m.matches(&name, span.as_str())) {
+                    debug!("removing not allowed property '{}'", span.as_str());
+                    result.push_str(&xml[copied_to..prev_end]);
+                    copied_to = span.end();
+                }
+                prev_end = span.end();
             }
+            _ => {}
         }
     }
-    if result {
-        Some(format!("{line}\n"))
-    } else {
-        None
+    if let Some(name) = open_elements.last() {
+        bail!("element '{}' is not closed", name);
+    }
+    result.push_str(&xml[copied_to..]);
+    Ok(result)
+}
+
+/// Removes ignored properties of the node fetched as JSON; each property is checked as it would
+/// be written in `.content.xml` (`name="value"`).
+pub(crate) fn cleanup_json_props(node: &mut Value, ignore_properties: &[IgnoreProp]) {
+    let matchers = prop_matchers(ignore_properties);
+    if let Some(props) = node.as_object_mut() {
+        props.retain(|name, value| {
+            let prop = format!(r#"{}="{}""#, name, json_prop_value(value));
+            value.is_object() || !matchers.iter().any(|m| m.matches(name, &prop))
+        });
+    }
+}
+
+fn json_prop_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Array(values) => format!(
+            "[{}]",
+            values
+                .iter()
+                .map(json_prop_value)
+                .collect::<Vec<String>>()
+                .join(",")
+        ),
+        other => other.to_string(),
     }
 }
 
 pub(crate) fn mv_bundle_back(tmp_dir: &TempDir, bundle: &Bundle) -> Result<()> {
-    for file in bundle.paths() {
-        mv_files_back(tmp_dir, &Path::new(file))?;
+    for bundle_path in bundle.paths() {
+        let target = Path::new(bundle_path.path());
+        if bundle_path.filter().has_rules() && target.is_dir() {
+            merge_filtered_files_back(tmp_dir, &target, bundle_path.filter())?;
+        } else {
+            mv_files_back(tmp_dir, &target)?;
+        }
     }
     Ok(())
 }
 
+/// Package built with include/exclude rules holds only the covered content, so instead of
+/// replacing the whole directory only covered local files are removed before moving.
+fn merge_filtered_files_back(tmp_dir: &TempDir, target: &Path, filter: &PathFilter) -> Result<()> {
+    let source = tmp_dir.path().join(target.after_root()?);
+    info!(
+        "merging filtered files from {} to {}",
+        source.display(),
+        target.full()
+    );
+    list_files(&source);
+    let rules = filter.rules()?;
+    for entry in WalkDir::new(target.full())
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_file())
+    {
+        let jcr_path = Path::new(entry.path().to_string_lossy()).content()?;
+        if rules.covers(&jcr_path) {
+            debug!("removing {} covered by filter", entry.path().display());
+            fs::remove_file(entry.path())?;
+        }
+    }
+    if !source.exists() {
+        return Ok(());
+    }
+    let cp = dir::CopyOptions {
+        content_only: true,
+        overwrite: true,
+        ..dir::CopyOptions::default()
+    };
+    dir::move_dir(&source, target.full(), &cp).context(format!(
+        "failed to move dir from {} to {}",
+        source.display(),
+        target.full()
+    ))?;
+    Ok(())
+}
+
 pub(crate) fn mv_files_back(tmp_dir: &TempDir, target: &Path) -> Result<()> {
-    let source = tmp_dir.path().join(target.after_root());
+    let source = tmp_dir.path().join(target.after_root()?);
     info!(
         "moving files from {} to {}",
         source.display(),
@@ -211,10 +335,11 @@ mod test {
     use super::*;
     use std::fs::create_dir_all;
     use std::fs::{read_to_string, File};
+    use std::io::prelude::*;
     use tempfile::{NamedTempFile, TempDir};
 
     #[test]
-    fn test_allowed_prop() {
+    fn test_prop_matchers() {
         // given
         let ignore_properties = &[
             IgnoreProp {
@@ -222,28 +347,31 @@ mod test {
                 value: "cq:lastModified".to_string(),
             },
             IgnoreProp {
-                ignore_type: IgnoreType::Contains,
+                ignore_type: IgnoreType::Name,
                 value: "testProperty".to_string(),
             },
+            IgnoreProp {
+                ignore_type: IgnoreType::Regex,
+                value: r#"^jcr:.*="\[]"$"#.to_string(),
+            },
         ];
         let test_cases = &[
-            ("cq:lastModified", None),
-            ("testProperty", None),
-            ("cq:lastModifiedBy", None),
-            ("    cq:lastModifiedBy", None),
-            ("cq:lastReplicated", Some("cq:lastReplicated\n".to_string())),
-            (
-                "    cq:lastReplicated",
-                Some("    cq:lastReplicated\n".to_string()),
-            ),
+            ("cq:lastModified", r#"cq:lastModified="x""#, true),
+            ("cq:lastModifiedBy", r#"cq:lastModifiedBy="x""#, true),
+            ("testProperty", r#"testProperty="x""#, true),
+            ("testPropertyOther", r#"testPropertyOther="x""#, false),
+            ("jcr:mixinTypes", r#"jcr:mixinTypes="[]""#, true),
+            ("jcr:mixinTypes", r#"jcr:mixinTypes="[cq:X]""#, false),
+            ("cq:lastReplicated", r#"cq:lastReplicated="x""#, false),
         ];
 
-        for test_case in test_cases {
-            // when
-            let result = allowed_prop(test_case.0, ignore_properties);
+        // when
+        let matchers = prop_matchers(ignore_properties);
 
-            // then
-            assert_eq!(result, test_case.1);
+        // then
+        for (name, prop, expected) in test_cases {
+            let matches = matchers.iter().any(|m| m.matches(name, prop));
+            assert_eq!(matches, *expected, "property: {prop}");
         }
     }
 
@@ -371,33 +499,129 @@ mod test {
     }
 
     #[test]
-    fn test_cleanup_files_with_type_contains() -> Result<()> {
+    fn test_mv_bundle_back_keeps_files_excluded_by_filter() -> Result<()> {
         let _ = pretty_env_logger::try_init();
         // given
-        let ignore_properties = vec![IgnoreProp {
-            ignore_type: IgnoreType::Contains,
-            value: "property-to-ignore".into(),
-        }];
+        let src_dir = TempDir::new()?;
+        create_dir_all(src_dir.path().join("jcr_root/dam/asset"))?;
+        File::create(src_dir.path().join("jcr_root/dam/asset/original"))?.write_all(b"new")?;
+
+        let target_dir = TempDir::new()?;
+        create_dir_all(target_dir.path().join("jcr_root/dam/asset/renditions"))?;
+        File::create(target_dir.path().join("jcr_root/dam/asset/original"))?.write_all(b"old")?;
+        File::create(target_dir.path().join("jcr_root/dam/asset/stale"))?;
+        File::create(
+            target_dir
+                .path()
+                .join("jcr_root/dam/asset/renditions/thumb"),
+        )?;
+
+        let dam = target_dir.path().join("jcr_root/dam");
+        let bundle: Bundle = toml::from_str(&format!(
+            r#"
+            name = "dam"
+            paths = [{{ path = '{}', exclude = [".*/renditions(/.*)?"] }}]
+        "#,
+            dam.display()
+        ))?;
+
+        // when
+        mv_bundle_back(&src_dir, &bundle)?;
+
+        // then
+        assert_eq!(read_to_string(dam.join("asset/original"))?, "new");
+        assert!(!dam.join("asset/stale").exists());
+        assert!(dam.join("asset/renditions/thumb").exists());
+        Ok(())
+    }
+
+    const CONTENT_XML_TO_CLEAN: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<jcr:root xmlns:cq="http://www.day.com/jcr/cq/1.0" xmlns:jcr="http://www.jcp.org/jcr/1.0"
+    jcr:primaryType="cq:Page">
+    <jcr:content
+        jcr:created="{Date}2021-07-29T10:00:00.000+02:00"
+        jcr:mixinTypes="[]"
+        jcr:primaryType="cq:PageContent"
+        jcr:title="Title"
+        cq:lastModifiedBy="admin"/>
+</jcr:root>
+"#;
+
+    fn cleanup_content_xml(ignore_properties: &[IgnoreProp], content: &str) -> Result<String> {
         let tmp_dir = TempDir::new()?;
         create_dir_all(tmp_dir.path().join("jcr_root"))?;
         let mut file = File::create(tmp_dir.path().join("jcr_root/.content.xml"))?;
-        file.write_all(
-            r#"some-property
-property-to-ignore
-other-property"#
-                .as_bytes(),
+        file.write_all(content.as_bytes())?;
+
+        cleanup_files(ignore_properties, &tmp_dir)?;
+
+        Ok(read_to_string(
+            tmp_dir.path().join("jcr_root/.content.xml"),
+        )?)
+    }
+
+    #[test]
+    fn test_cleanup_json_props() -> Result<()> {
+        // given
+        let ignore_properties = vec![
+            IgnoreProp {
+                ignore_type: IgnoreType::Name,
+                value: "jcr:created".into(),
+            },
+            IgnoreProp {
+                ignore_type: IgnoreType::Regex,
+                value: r#"^jcr:mixinTypes="\[]"$"#.into(),
+            },
+        ];
+        let mut node: Value = serde_json::from_str(
+            r#"{
+                "jcr:primaryType": "cq:Page",
+                "jcr:created": "Thu Jul 29 2021 10:00:00 GMT+0200",
+                "jcr:mixinTypes": [],
+                "tags": ["a", "b"],
+                "jcr:content": {"jcr:created": "x"}
+            }"#,
         )?;
 
         // when
-        cleanup_files(&ignore_properties, &tmp_dir)?;
+        cleanup_json_props(&mut node, &ignore_properties);
 
         // then
-        let content = read_to_string(tmp_dir.path().join("jcr_root/.content.xml"))?;
+        assert_eq!(
+            node,
+            serde_json::json!({
+                "jcr:primaryType": "cq:Page",
+                "tags": ["a", "b"],
+                "jcr:content": {"jcr:created": "x"}
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cleanup_files_with_type_contains() -> Result<()> {
+        let _ = pretty_env_logger::try_init();
+        // given
+        let ignore_properties = vec![IgnoreProp {
+            ignore_type: IgnoreType::Contains,
+            value: "jcr:created".into(),
+        }];
+
+        // when
+        let content = cleanup_content_xml(&ignore_properties, CONTENT_XML_TO_CLEAN)?;
 
+        // then
         assert_eq!(
             content,
-            r#"some-property
-other-property
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<jcr:root xmlns:cq="http://www.day.com/jcr/cq/1.0" xmlns:jcr="http://www.jcp.org/jcr/1.0"
+    jcr:primaryType="cq:Page">
+    <jcr:content
+        jcr:mixinTypes="[]"
+        jcr:primaryType="cq:PageContent"
+        jcr:title="Title"
+        cq:lastModifiedBy="admin"/>
+</jcr:root>
 "#
         );
         Ok(())
@@ -409,65 +633,112 @@ other-property
         // given
         let ignore_properties = vec![IgnoreProp {
             ignore_type: IgnoreType::Regex,
-            value: ".*to.*".into(),
+            value: r#"^jcr:.*="\[]"$"#.into(),
         }];
-        let tmp_dir = TempDir::new()?;
-        create_dir_all(tmp_dir.path().join("jcr_root"))?;
-        let mut file = File::create(tmp_dir.path().join("jcr_root/.content.xml"))?;
-        file.write_all(
-            r#"some-property
-property-to-ignore
-other-to-property
-"#
-            .as_bytes(),
-        )?;
 
         // when
-        cleanup_files(&ignore_properties, &tmp_dir)?;
+        let content = cleanup_content_xml(&ignore_properties, CONTENT_XML_TO_CLEAN)?;
 
         // then
-        let content = read_to_string(tmp_dir.path().join("jcr_root/.content.xml"))?;
-
         assert_eq!(
             content,
-            r#"some-property
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<jcr:root xmlns:cq="http://www.day.com/jcr/cq/1.0" xmlns:jcr="http://www.jcp.org/jcr/1.0"
+    jcr:primaryType="cq:Page">
+    <jcr:content
+        jcr:created="{Date}2021-07-29T10:00:00.000+02:00"
+        jcr:primaryType="cq:PageContent"
+        jcr:title="Title"
+        cq:lastModifiedBy="admin"/>
+</jcr:root>
 "#
         );
         Ok(())
     }
 
     #[test]
-    fn test_cleanup_files_with_type_regex_and_complex_value() -> Result<()> {
+    fn test_cleanup_files_with_last_property_removed() -> Result<()> {
         let _ = pretty_env_logger::try_init();
         // given
         let ignore_properties = vec![IgnoreProp {
-            ignore_type: IgnoreType::Regex,
-            value: r".*='\[]'".into(),
+            ignore_type: IgnoreType::Name,
+            value: "cq:lastModifiedBy".into(),
         }];
-        let tmp_dir = TempDir::new()?;
-        create_dir_all(tmp_dir.path().join("jcr_root"))?;
-        let mut file = File::create(tmp_dir.path().join("jcr_root/.content.xml"))?;
-        file.write_all(
-            r#"jcr:mixinTypes='[]'
-other-property
-"#
-            .as_bytes(),
-        )?;
 
         // when
-        cleanup_files(&ignore_properties, &tmp_dir)?;
+        let content = cleanup_content_xml(&ignore_properties, CONTENT_XML_TO_CLEAN)?;
 
         // then
-        let content = read_to_string(tmp_dir.path().join("jcr_root/.content.xml"))?;
+        assert_eq!(
+            content,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<jcr:root xmlns:cq="http://www.day.com/jcr/cq/1.0" xmlns:jcr="http://www.jcp.org/jcr/1.0"
+    jcr:primaryType="cq:Page">
+    <jcr:content
+        jcr:created="{Date}2021-07-29T10:00:00.000+02:00"
+        jcr:mixinTypes="[]"
+        jcr:primaryType="cq:PageContent"
+        jcr:title="Title"/>
+</jcr:root>
+"#
+        );
+        Ok(())
+    }
 
+    #[test]
+    fn test_cleanup_files_with_type_name_and_nested_elements() -> Result<()> {
+        let _ = pretty_env_logger::try_init();
+        // given
+        let ignore_properties = vec![IgnoreProp {
+            ignore_type: IgnoreType::Name,
+            value: "jcr:primaryType".into(),
+        }];
+
+        // when
+        let content = cleanup_content_xml(
+            &ignore_properties,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<jcr:root xmlns:jcr="http://www.jcp.org/jcr/1.0"
+    jcr:primaryType="nt:unstructured">
+    <item jcr:primaryType="nt:unstructured" title="a > b">
+        <nested
+            jcr:primaryType="nt:unstructured"/>
+    </item>
+</jcr:root>
+"#,
+        )?;
+
+        // then
         assert_eq!(
             content,
-            r#"other-property
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<jcr:root xmlns:jcr="http://www.jcp.org/jcr/1.0">
+    <item title="a > b">
+        <nested/>
+    </item>
+</jcr:root>
 "#
         );
         Ok(())
     }
 
+    #[test]
+    fn test_cleanup_files_keeps_namespaces() -> Result<()> {
+        let _ = pretty_env_logger::try_init();
+        // given
+        let ignore_properties = vec![IgnoreProp {
+            ignore_type: IgnoreType::Contains,
+            value: "http://www.day.com/jcr/cq/1.0".into(),
+        }];
+
+        // when
+        let content = cleanup_content_xml(&ignore_properties, CONTENT_XML_TO_CLEAN)?;
+
+        // then
+        assert_eq!(content, CONTENT_XML_TO_CLEAN);
+        Ok(())
+    }
+
     #[test]
     fn test_cleanup_files_with_type_regex_broken_value() -> Result<()> {
         let _ = pretty_env_logger::try_init();
@@ -476,28 +747,29 @@ other-property
             ignore_type: IgnoreType::Regex,
             value: "[".into(),
         }];
-        let tmp_dir = TempDir::new()?;
-        create_dir_all(tmp_dir.path().join("jcr_root"))?;
-        let mut file = File::create(tmp_dir.path().join("jcr_root/.content.xml"))?;
-        file.write_all(
-            r#"jcr:mixinTypes='[]'
-other-property
-"#
-            .as_bytes(),
-        )?;
 
         // when
-        cleanup_files(&ignore_properties, &tmp_dir)?;
+        let content = cleanup_content_xml(&ignore_properties, CONTENT_XML_TO_CLEAN)?;
 
         // then
-        let content = read_to_string(tmp_dir.path().join("jcr_root/.content.xml"))?;
+        assert_eq!(content, CONTENT_XML_TO_CLEAN);
+        Ok(())
+    }
 
-        assert_eq!(
-            content,
-            r#"jcr:mixinTypes='[]'
-other-property
-"#
-        );
+    #[test]
+    fn test_cleanup_files_with_incorrect_xml() -> Result<()> {
+        let _ = pretty_env_logger::try_init();
+        // given
+        let ignore_properties = vec![IgnoreProp {
+            ignore_type: IgnoreType::Contains,
+            value: "property-to-ignore".into(),
+        }];
+
+        // when
+        let content = cleanup_content_xml(&ignore_properties, "<a property-to-ignore=\"x\"")?;
+
+        // then
+        assert_eq!(content, "<a property-to-ignore=\"x\"");
         Ok(())
     }
 
@@ -506,29 +778,12 @@ other-property
         let _ = pretty_env_logger::try_init();
         // given
         let ignore_properties = vec![];
-        let tmp_dir = TempDir::new()?;
-        create_dir_all(tmp_dir.path().join("jcr_root"))?;
-        let mut file = File::create(tmp_dir.path().join("jcr_root/.content.xml"))?;
-        file.write_all(
-            r#"some-property
-property
-other-property"#
-                .as_bytes(),
-        )?;
 
         // when
-        cleanup_files(&ignore_properties, &tmp_dir)?;
+        let content = cleanup_content_xml(&ignore_properties, CONTENT_XML_TO_CLEAN)?;
 
         // then
-        let content = read_to_string(tmp_dir.path().join("jcr_root/.content.xml"))?;
-
-        assert_eq!(
-            content,
-            r#"some-property
-property
-other-property
-"#
-        );
+        assert_eq!(content, CONTENT_XML_TO_CLEAN);
         Ok(())
     }
 }
@@ -1,11 +1,19 @@
-use crate::cfg::Instance;
-use anyhow::Result;
+use crate::cfg::{Auth, HttpSettings, Instance};
+use crate::progress;
+use anyhow::{bail, Context, Result};
 use base64::encode;
-use bytes::Bytes;
-use log::warn;
+use indicatif::ProgressBar;
+use log::{info, warn};
 use reqwest::blocking::multipart;
-use reqwest::blocking::{Client as HttpClient, Response as Resp};
+use reqwest::blocking::{Client as HttpClient, RequestBuilder, Response as Resp};
+use reqwest::header::{AUTHORIZATION, COOKIE};
+use reqwest::{Certificate, StatusCode};
+use serde::de::DeserializeOwned;
+use std::fs::{self, File};
+use std::io::prelude::*;
 use std::path::Path;
+use std::thread;
+use std::time::Duration;
 
 // the `Option<Resp>` here is not-so-elegant solution for mocking
 // the response in the mock client implementations
@@ -13,12 +21,40 @@ use std::path::Path;
 pub(crate) struct Response(pub(crate) Option<Resp>);
 
 impl Response {
-    pub(crate) fn bytes(self) -> Result<Bytes> {
+    pub(crate) fn status(&self) -> Option<StatusCode> {
+        self.0.as_ref().map(Resp::status)
+    }
+
+    pub(crate) fn error_for_status(self) -> Result<Self> {
+        match self.status() {
+            Some(status) if !status.is_success() => bail!("server responded with {}", status),
+            _ => Ok(self),
+        }
+    }
+
+    pub(crate) fn json<T: DeserializeOwned>(self) -> Result<T> {
+        Ok(serde_json::from_str(&self.text()?)?)
+    }
+
+    /// Size of the body announced by the server, if any.
+    pub(crate) fn content_length(&self) -> Option<u64> {
+        self.0.as_ref().and_then(Resp::content_length)
+    }
+
+    /// Streams the body to `w` without keeping it in memory; returns number of copied bytes.
+    pub(crate) fn copy_to(self, w: &mut impl Write) -> Result<u64> {
+        match self.0 {
+            Some(mut resp) => Ok(resp.copy_to(w)?),
+            None => bail!("no response available"),
+        }
+    }
+
+    pub(crate) fn text(self) -> Result<String> {
         Ok(if let Some(resp) = self.0 {
-            resp.bytes()?
+            resp.text()?
         } else {
             warn!("no response field available");
-            Bytes::default()
+            String::new()
         })
     }
 }
@@ -31,11 +67,66 @@ pub(crate) trait Client {
 
 pub(crate) struct AemClient<'a> {
     instance: &'a Instance,
+    /// client of GET requests, i.e. downloads and status checks
+    client: HttpClient,
+    /// client of POST requests, i.e. uploads and package commands
+    cmd_client: HttpClient,
+    progress: bool,
 }
 
 impl<'a> AemClient<'a> {
-    pub(crate) fn new(instance: &'a Instance) -> Self {
-        Self { instance }
+    pub(crate) fn new(instance: &'a Instance) -> Result<Self> {
+        let settings = instance.http();
+        Ok(Self {
+            instance,
+            client: build_client(settings, settings.read_timeout_secs())?,
+            cmd_client: build_client(settings, settings.cmd_timeout_secs())?,
+            progress: true,
+        })
+    }
+
+    pub(crate) fn instance(&self) -> &Instance {
+        self.instance
+    }
+
+    /// Turns off progress bars of uploads, e.g. when more clients upload at the same time.
+    pub(crate) fn without_progress(mut self) -> Self {
+        self.progress = false;
+        self
+    }
+}
+
+/// Builds the client of requests to the instance; proxies are taken from
+/// `HTTP_PROXY`/`HTTPS_PROXY` variables.
+///
+/// The blocking client applies its timeout to sending the request with waiting for the response
+/// and then to each read of the body, so streamed downloads aren't cut off by `timeout_secs`,
+/// while uploads and synchronous package commands (e.g. install) get a long, but finite limit.
+fn build_client(settings: &HttpSettings, timeout_secs: u64) -> Result<HttpClient> {
+    let mut builder = HttpClient::builder()
+        .connect_timeout(Duration::from_secs(settings.connect_timeout_secs()))
+        .timeout(Duration::from_secs(timeout_secs));
+    if let Some(ca_cert) = settings.ca_cert() {
+        let pem =
+            fs::read(ca_cert).context(format!("failed to read CA certificate {}", ca_cert))?;
+        builder = builder.add_root_certificate(
+            Certificate::from_pem(&pem).context(format!("incorrect CA certificate {}", ca_cert))?,
+        );
+    }
+    if settings.insecure() {
+        warn!("certificates of the instance are not verified");
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    Ok(builder.build()?)
+}
+
+fn should_retry(result: &reqwest::Result<Resp>) -> bool {
+    match result {
+        Ok(resp) => matches!(
+            resp.status(),
+            StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+        ),
+        Err(e) => e.is_connect() || e.is_timeout(),
     }
 }
 
@@ -43,45 +134,77 @@ fn encoded_creds(ins: &Instance) -> String {
     encode(format!("{}:{}", ins.user(), ins.pass()))
 }
 
+fn authorize(request: RequestBuilder, ins: &Instance) -> Result<RequestBuilder> {
+    Ok(match ins.auth() {
+        Auth::Basic => request.header(AUTHORIZATION, format!("Basic {}", encoded_creds(ins))),
+        Auth::Bearer { token } => request.bearer_auth(token),
+        // token files are read when the config is loaded, see `cfgmgr::handle_cfg_load`
+        Auth::TokenFile { path } => bail!("token from {} was not read", path),
+        Auth::LoginToken { token } => request.header(COOKIE, format!("login-token={}", token)),
+    })
+}
+
 impl Client for AemClient<'_> {
     fn get<S: Into<String>>(&self, path: S) -> Result<Response> {
         let ins = self.instance;
         let path = format!("{}{}", ins.addr(), path.into());
-        let client = HttpClient::new();
+        let settings = ins.http();
+        let mut interval = Duration::from_millis(settings.retry_interval_ms());
+        // only GET requests are retried, as they are idempotent
+        for attempt in 1..=settings.retries() {
+            let result = authorize(self.client.get(&path), ins)?.send();
+            if !should_retry(&result) {
+                return Ok(Response(Some(result?)));
+            }
+            match result {
+                Ok(resp) => warn!("{} responded with {}", path, resp.status()),
+                Err(e) => warn!("request to {} failed: {}", path, e),
+            }
+            info!(
+                "retrying in {}ms ({}/{})",
+                interval.as_millis(),
+                attempt,
+                settings.retries()
+            );
+            thread::sleep(interval);
+            interval *= 2;
+        }
         Ok(Response(Some(
-            client
-                .get(path)
-                .header("Authorization", format!("Basic {}", encoded_creds(ins)))
-                .send()?,
+            authorize(self.client.get(&path), ins)?.send()?,
         )))
     }
 
     fn post<S: Into<String>>(&self, path: S) -> Result<Response> {
         let ins = self.instance;
         let path = format!("{}{}", ins.addr(), path.into());
-        let client = HttpClient::new();
         Ok(Response(Some(
-            client
-                .post(path)
-                .header("Authorization", format!("Basic {}", encoded_creds(ins)))
-                .send()?,
+            authorize(self.cmd_client.post(path), ins)?.send()?,
         )))
     }
 
     fn post_file<S: Into<String>, A: AsRef<Path>>(&self, path: S, filepath: A) -> Result<Response> {
         let ins = self.instance;
         let path = format!("{}{}", ins.addr(), path.into());
-        let client = HttpClient::new();
-        let form = multipart::Form::new()
-            .file("package", filepath)
-            .expect("failed to create multipart form");
-        Ok(Response(Some(
-            client
-                .post(path)
-                .header("Authorization", format!("Basic {}", encoded_creds(ins)))
-                .multipart(form)
-                .send()?,
-        )))
+        let filepath = filepath.as_ref();
+        let file =
+            File::open(filepath).context(format!("failed to open {}", filepath.display()))?;
+        let len = file.metadata()?.len();
+        let bar = if self.progress {
+            progress::transfer(Some(len), "uploading")
+        } else {
+            ProgressBar::hidden()
+        };
+        // the file is read while sending, so it's never loaded into memory as a whole
+        let mut part = multipart::Part::reader_with_length(bar.wrap_read(file), len);
+        if let Some(name) = filepath.file_name() {
+            part = part.file_name(name.to_string_lossy().into_owned());
+        }
+        let form = multipart::Form::new().part("package", part);
+        let resp = authorize(self.cmd_client.post(path), ins)?
+            .multipart(form)
+            .send()?;
+        bar.finish_and_clear();
+        Ok(Response(Some(resp)))
     }
 }
 
@@ -89,6 +212,8 @@ impl Client for AemClient<'_> {
 mod test {
     use super::*;
     use base64::decode;
+    use std::net::TcpListener;
+    use tempfile::TempDir;
 
     #[test]
     fn test_encoced_creds() {
@@ -104,4 +229,297 @@ mod test {
             "user-name:password"
         );
     }
+
+    fn auth_headers(instance: &Instance) -> Result<(Option<String>, Option<String>)> {
+        let request =
+            authorize(HttpClient::new().get("http://localhost:4502"), instance)?.build()?;
+        let header = |name| {
+            request
+                .headers()
+                .get(name)
+                .map(|v: &reqwest::header::HeaderValue| v.to_str().unwrap().to_string())
+        };
+        Ok((header(AUTHORIZATION), header(COOKIE)))
+    }
+
+    #[test]
+    fn test_authorize() -> Result<()> {
+        // given
+        let basic = Instance::new("author", "http://localhost:4502", "admin", "admin");
+        let with_auth = |auth: &str| -> Result<Instance> {
+            Ok(toml::from_str(&format!(
+                r#"
+                name = "author"
+                addr = "http://localhost:4502"
+                auth = {}
+            "#,
+                auth
+            ))?)
+        };
+        let bearer = with_auth(r#"{ type = "bearer", token = "abc" }"#)?;
+        let login_token = with_auth(r#"{ type = "login_token", token = "xyz" }"#)?;
+
+        // then
+        assert_eq!(
+            auth_headers(&basic)?,
+            (Some("Basic YWRtaW46YWRtaW4=".into()), None)
+        );
+        assert_eq!(auth_headers(&bearer)?, (Some("Bearer abc".into()), None));
+        assert_eq!(
+            auth_headers(&login_token)?,
+            (None, Some("login-token=xyz".into()))
+        );
+        Ok(())
+    }
+
+    /// Serves given statuses, one connection per status, and returns the address of the server.
+    fn serve(statuses: &'static [u16]) -> Result<String> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = format!("http://{}", listener.local_addr()?);
+        thread::spawn(move || {
+            for (status, stream) in statuses.iter().zip(listener.incoming()) {
+                let mut stream = stream.unwrap();
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request).unwrap();
+                write!(
+                    stream,
+                    "HTTP/1.1 {} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                )
+                .unwrap();
+            }
+        });
+        Ok(addr)
+    }
+
+    /// Serves the raw HTTP response once and returns the address of the server.
+    fn serve_raw(response: &'static str) -> Result<String> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = format!("http://{}", listener.local_addr()?);
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        Ok(addr)
+    }
+
+    fn instance_with_http(addr: &str, http: &str) -> Result<Instance> {
+        Ok(toml::from_str(&format!(
+            r#"
+            name = "author"
+            addr = "{}"
+            http = {}
+        "#,
+            addr, http
+        ))?)
+    }
+
+    #[test]
+    fn test_get_retries_on_gateway_errors() -> Result<()> {
+        // given
+        let addr = serve(&[503, 502, 200])?;
+        let instance = instance_with_http(&addr, "{ retries = 2, retry_interval_ms = 1 }")?;
+        let client = AemClient::new(&instance)?;
+
+        // when
+        let resp = client.get("/")?;
+
+        // then
+        assert_eq!(resp.status(), Some(StatusCode::OK));
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_returns_last_response_when_retries_exhausted() -> Result<()> {
+        // given
+        let addr = serve(&[503, 503, 200])?;
+        let instance = instance_with_http(&addr, "{ retries = 1, retry_interval_ms = 1 }")?;
+        let client = AemClient::new(&instance)?;
+
+        // when
+        let resp = client.get("/")?;
+
+        // then
+        assert_eq!(resp.status(), Some(StatusCode::SERVICE_UNAVAILABLE));
+        Ok(())
+    }
+
+    #[test]
+    fn test_post_is_not_retried() -> Result<()> {
+        // given
+        let addr = serve(&[503, 200])?;
+        let instance = instance_with_http(&addr, "{ retries = 2, retry_interval_ms = 1 }")?;
+        let client = AemClient::new(&instance)?;
+
+        // when
+        let resp = client.post("/")?;
+
+        // then
+        assert_eq!(resp.status(), Some(StatusCode::SERVICE_UNAVAILABLE));
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_to() -> Result<()> {
+        // given
+        let addr =
+            serve_raw("HTTP/1.1 200 OK\r\nContent-Length: 7\r\nConnection: close\r\n\r\npackage")?;
+        let instance = Instance::new("author", &addr, "admin", "admin");
+        let resp = AemClient::new(&instance)?.get("/")?;
+        let mut body = Vec::new();
+
+        // when
+        let copied = resp.copy_to(&mut body)?;
+
+        // then
+        assert_eq!(copied, 7);
+        assert_eq!(body, b"package");
+        Ok(())
+    }
+
+    /// Serves the parts of the raw HTTP response with the delay before each of them.
+    fn serve_slowly(parts: &'static [&'static str], delay: Duration) -> Result<String> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = format!("http://{}", listener.local_addr()?);
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            for part in parts {
+                thread::sleep(delay);
+                stream.write_all(part.as_bytes()).unwrap();
+                stream.flush().unwrap();
+            }
+        });
+        Ok(addr)
+    }
+
+    #[test]
+    fn test_read_timeout_is_not_total_timeout_of_download() -> Result<()> {
+        // given
+        let addr = serve_slowly(
+            &[
+                "HTTP/1.1 200 OK\r\nContent-Length: 7\r\nConnection: close\r\n\r\n",
+                "pack",
+                "age",
+            ],
+            Duration::from_millis(600),
+        )?;
+        let instance = instance_with_http(&addr, "{ read_timeout_secs = 1 }")?;
+        let resp = AemClient::new(&instance)?.get("/")?;
+        let mut body = Vec::new();
+
+        // when
+        resp.copy_to(&mut body)?;
+
+        // then
+        assert_eq!(body, b"package");
+        Ok(())
+    }
+
+    #[test]
+    fn test_post_waits_longer_than_read_timeout() -> Result<()> {
+        // given
+        let addr = serve_slowly(
+            &[
+                "",
+                "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            ],
+            Duration::from_millis(800),
+        )?;
+        let instance = instance_with_http(&addr, "{ read_timeout_secs = 1 }")?;
+        let client = AemClient::new(&instance)?;
+
+        // when
+        let resp = client.post("/")?;
+
+        // then
+        assert_eq!(resp.status(), Some(StatusCode::OK));
+        Ok(())
+    }
+
+    #[test]
+    fn test_post_fails_after_cmd_timeout() -> Result<()> {
+        // given
+        let addr = serve_slowly(
+            &[
+                "",
+                "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            ],
+            Duration::from_millis(800),
+        )?;
+        let instance =
+            instance_with_http(&addr, "{ read_timeout_secs = 10, cmd_timeout_secs = 1 }")?;
+        let client = AemClient::new(&instance)?;
+
+        // when
+        let res = client.post("/");
+
+        // then
+        assert!(res.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_to_when_body_is_truncated() -> Result<()> {
+        // given
+        let addr =
+            serve_raw("HTTP/1.1 200 OK\r\nContent-Length: 100\r\nConnection: close\r\n\r\npack")?;
+        let instance = Instance::new("author", &addr, "admin", "admin");
+        let resp = AemClient::new(&instance)?.get("/")?;
+
+        // when
+        let res = resp.copy_to(&mut Vec::new());
+
+        // then
+        assert!(res.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_to_when_response_is_missing() {
+        // when
+        let res = Response(None).copy_to(&mut Vec::new());
+
+        // then
+        assert_eq!(res.unwrap_err().to_string(), "no response available");
+    }
+
+    #[test]
+    fn test_post_file_when_file_is_missing() -> Result<()> {
+        // given
+        let tmp_dir = TempDir::new()?;
+        let missing = tmp_dir.path().join("pkg.zip");
+        let instance = Instance::new("author", "http://localhost:4502", "admin", "admin");
+        let client = AemClient::new(&instance)?;
+
+        // when
+        let res = client.post_file("/", &missing);
+
+        // then
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            format!("failed to open {}", missing.display())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_new_with_missing_ca_cert() -> Result<()> {
+        // given
+        let instance =
+            instance_with_http("http://localhost:4502", r#"{ ca_cert = "missing.pem" }"#)?;
+
+        // when
+        let res = AemClient::new(&instance);
+
+        // then
+        assert_eq!(
+            res.err().unwrap().to_string(),
+            "failed to read CA certificate missing.pem"
+        );
+        Ok(())
+    }
 }
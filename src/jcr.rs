@@ -0,0 +1,131 @@
+//! Reading content of the instance through the Sling default GET servlet.
+use crate::http::Client;
+use anyhow::{bail, Context, Result};
+use log::debug;
+use reqwest::StatusCode;
+use serde_json::{Map, Value};
+use std::io::prelude::*;
+
+const PRIMARY_TYPE: &str = "jcr:primaryType";
+
+/// Fetches the node with its properties and descendants up to `depth` levels below it.
+pub(crate) fn get_node(client: &impl Client, path: &str, depth: u32) -> Result<Value> {
+    let resp = client.get(format!("{}.{}.json", encode_path(path), depth))?;
+    debug!("get node response: {:#?}", resp);
+    if resp.status() == Some(StatusCode::NOT_FOUND) {
+        bail!("path {} doesn't exist on the instance", path);
+    }
+    resp.error_for_status()
+        .and_then(|resp| resp.json())
+        .context(format!("failed to read node {}", path))
+}
+
+/// Writes tree of the node and its descendants, with names and primary types.
+pub(crate) fn write_tree(path: &str, node: &Value, w: &mut impl Write) -> Result<()> {
+    writeln!(w, "{}{}", path, primary_type(node))?;
+    write_children(node, "", w)
+}
+
+fn write_children(node: &Value, indent: &str, w: &mut impl Write) -> Result<()> {
+    let children: Vec<(&String, &Value)> = node
+        .as_object()
+        .map(Map::iter)
+        .into_iter()
+        .flatten()
+        .filter(|(_, value)| value.is_object())
+        .collect();
+    for (idx, (name, child)) in children.iter().enumerate() {
+        let is_last = idx == children.len() - 1;
+        let (branch, nested_indent) = if is_last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        writeln!(w, "{}{}{}{}", indent, branch, name, primary_type(child))?;
+        write_children(child, &format!("{}{}", indent, nested_indent), w)?;
+    }
+    Ok(())
+}
+
+fn primary_type(node: &Value) -> String {
+    node.get(PRIMARY_TYPE)
+        .and_then(Value::as_str)
+        .map(|t| format!(" [{}]", t))
+        .unwrap_or_default()
+}
+
+/// Escapes characters which have special meaning in URLs but are allowed in JCR names.
+fn encode_path(path: &str) -> String {
+    path.replace('%', "%25")
+        .replace('#', "%23")
+        .replace('?', "%3F")
+        .replace(' ', "%20")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutils::ClientSpy;
+
+    #[test]
+    fn test_get_node() -> Result<()> {
+        // given
+        let spy = ClientSpy::with_responses(&[(200, r#"{"jcr:primaryType":"cq:Page"}"#)]);
+
+        // when
+        let node = get_node(&spy, "/content/my site", 2)?;
+
+        // then
+        assert_eq!(spy.get_req(), "/content/my%20site.2.json");
+        assert_eq!(node["jcr:primaryType"], "cq:Page");
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_node_when_not_found() {
+        // given
+        let spy = ClientSpy::with_responses(&[(404, "")]);
+
+        // when
+        let res = get_node(&spy, "/content/missing", 1);
+
+        // then
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "path /content/missing doesn't exist on the instance"
+        );
+    }
+
+    #[test]
+    fn test_write_tree() -> Result<()> {
+        // given
+        let node: Value = serde_json::from_str(
+            r#"{
+                "jcr:primaryType": "cq:Page",
+                "jcr:content": {
+                    "jcr:primaryType": "cq:PageContent",
+                    "jcr:title": "Site",
+                    "root": {"jcr:primaryType": "nt:unstructured"}
+                },
+                "en": {"jcr:primaryType": "cq:Page"},
+                "untyped": {}
+            }"#,
+        )?;
+        let mut output = Vec::new();
+
+        // when
+        write_tree("/content/site", &node, &mut output)?;
+
+        // then
+        assert_eq!(
+            String::from_utf8(output)?,
+            r#"/content/site [cq:Page]
+├── jcr:content [cq:PageContent]
+│   └── root [nt:unstructured]
+├── en [cq:Page]
+└── untyped
+"#
+        );
+        Ok(())
+    }
+}
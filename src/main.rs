@@ -1,33 +1,53 @@
 use crate::cmd::Opt;
-use anyhow::Result;
 use log::{debug, info, warn};
 use std::env;
+use std::process::ExitCode;
 use structopt::StructOpt;
 
 mod args;
+mod backup;
 mod cfg;
+mod cfgedit;
 mod cfgmgr;
 mod cmd;
+mod diff;
 mod fsops;
 mod http;
+mod jcr;
 mod path;
 mod pkg;
 mod pkgdir;
 mod pkgmgr;
+mod platform;
+mod progress;
+mod snapshot;
+mod watch;
 
 #[cfg(test)]
 mod testutils;
 
-fn main() -> Result<()> {
-    let opt = Opt::from_args();
+fn main() -> ExitCode {
+    let opt = match Opt::clap().get_matches_safe() {
+        Ok(matches) => Opt::from_clap(&matches),
+        // help and version are printed to stdout and exit with 0
+        Err(e) if !e.use_stderr() => e.exit(),
+        Err(e) => {
+            eprintln!("{}", e.message);
+            return ExitCode::from(cmd::ERROR);
+        }
+    };
     setup_log_level_for_logger(&opt);
     pretty_env_logger::init();
     debug!("parsed opts: {:#?}", opt);
     debug!("current working dir: {:?}", env::current_dir());
     info!("starting");
-    cmd::handle(&opt, &mut std::io::stdout())?;
-
-    Ok(())
+    match cmd::handle(&opt, &mut std::io::stdout()) {
+        Ok(exit_code) => exit_code,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            ExitCode::from(cmd::ERROR)
+        }
+    }
 }
 
 fn setup_log_level_for_logger(opt: &Opt) {
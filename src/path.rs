@@ -1,5 +1,10 @@
+use crate::platform;
+use std::error::Error;
+use std::fmt;
 use std::path::Path as OsPath;
 
+const JCR_ROOT: &str = "jcr_root";
+
 #[derive(Debug, Clone, Default)]
 pub(crate) struct Path(String);
 
@@ -8,41 +13,81 @@ impl Path {
         Path(path.into())
     }
 
-    pub(crate) fn content(&self) -> String {
-        let path = &self.0;
-        let parts: Vec<&str> = path.split("jcr_root").collect();
-        assert_eq!(parts.len(), 2);
-        parts[1].into()
+    /// JCR path of the node represented by this path.
+    pub(crate) fn content(&self) -> Result<String, NoJcrRoot> {
+        let platform_path = after_last_root(&self.0)?;
+        Ok(platform::repository_path(
+            &platform_path,
+            platform::is_docview(&self.0),
+        ))
+    }
+
+    /// JCR path this path refers to: when it's inside of `jcr_root` directory, JCR path
+    /// of the node represented by it, otherwise the path itself, which has to be absolute.
+    pub(crate) fn jcr_path(&self) -> Result<String, NoJcrRoot> {
+        match self.content() {
+            Err(_) if self.0.starts_with('/') => Ok(self.0.clone()),
+            res => res,
+        }
     }
 
     pub(crate) fn full(&self) -> String {
         self.0.clone()
     }
 
-    pub(crate) fn after_root(&self) -> String {
-        let path = &self.0;
-        let parts: Vec<&str> = path.split("jcr_root").collect();
-        assert_eq!(parts.len(), 2);
-        format!("jcr_root{}", parts[1])
+    /// Path starting from the `jcr_root` directory.
+    pub(crate) fn after_root(&self) -> Result<String, NoJcrRoot> {
+        Ok(format!("{}{}", JCR_ROOT, after_last_root(&self.0)?))
     }
 
     pub(crate) fn is_dir(&self) -> bool {
         OsPath::new(&self.0).is_dir()
     }
 
-    pub(crate) fn parent_from_root(&self) -> String {
+    pub(crate) fn parent_from_root(&self) -> Result<String, NoJcrRoot> {
         let parent = OsPath::new(&self.full())
             .parent()
             .unwrap_or_else(|| OsPath::new("/"))
             .display()
             .to_string();
-        let parts: Vec<&str> = parent.split("jcr_root").collect();
-        assert_eq!(parts.len(), 2);
-        let path: String = parts[1].into();
-        format!("jcr_root{path}")
+        let path = after_last_root(&parent).map_err(|_| NoJcrRoot(self.full()))?;
+        Ok(format!("{JCR_ROOT}{path}"))
     }
 }
 
+/// Returns part of the `path` after the last `jcr_root` component. Only whole components
+/// are taken into account, so e.g. `/jcr_root_backup` is not treated as a `jcr_root` dir.
+fn after_last_root(path: &str) -> Result<String, NoJcrRoot> {
+    let is_sep = |c: char| c == '/' || c == '\\';
+    path.match_indices(JCR_ROOT)
+        .map(|(idx, _)| idx)
+        .filter(|&idx| {
+            let before = &path[..idx];
+            let after = &path[idx + JCR_ROOT.len()..];
+            (before.is_empty() || before.ends_with(is_sep))
+                && (after.is_empty() || after.starts_with(is_sep))
+        })
+        .last()
+        .map(|idx| path[idx + JCR_ROOT.len()..].to_string())
+        .ok_or_else(|| NoJcrRoot(path.to_string()))
+}
+
+/// Returned when the path is not inside of a `jcr_root` directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct NoJcrRoot(pub(crate) String);
+
+impl fmt::Display for NoJcrRoot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "path '{}' is not inside of a {} directory",
+            self.0, JCR_ROOT
+        )
+    }
+}
+
+impl Error for NoJcrRoot {}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -58,17 +103,123 @@ mod test {
         let content_path = path.content();
 
         // then
-        assert_eq!(content_path, "/content/abc");
+        assert_eq!(content_path, Ok("/content/abc".into()));
     }
 
     #[test]
-    #[should_panic]
     fn test_content_path_with_broken_path() {
         // given
         let path = Path::new("/home/zbychu/project/test/content/abc");
 
-        // should panic
-        path.content();
+        // when
+        let content_path = path.content();
+
+        // then
+        assert_eq!(
+            content_path,
+            Err(NoJcrRoot("/home/zbychu/project/test/content/abc".into()))
+        );
+    }
+
+    #[test]
+    fn test_content_path_with_nested_jcr_root() {
+        // given
+        let path = Path::new("/home/zbychu/jcr_root/project/test/jcr_root/content/abc");
+
+        // when
+        let content_path = path.content();
+
+        // then
+        assert_eq!(content_path, Ok("/content/abc".into()));
+    }
+
+    #[test]
+    fn test_content_path_with_jcr_root_as_part_of_name() {
+        let test_cases = vec![
+            (
+                "/home/jcr_root/content/my_jcr_root",
+                Ok("/content/my_jcr_root"),
+            ),
+            (
+                "/home/jcr_root/content/jcr_root_old/a",
+                Ok("/content/jcr_root_old/a"),
+            ),
+            ("/home/jcr_root_old/content", Err(())),
+            ("/home/project/jcr_root", Ok("/")),
+            ("C:\\project\\jcr_root\\content\\abc", Ok("/content/abc")),
+        ];
+
+        for (path, expected) in test_cases {
+            // when
+            let content_path = Path::new(path).content();
+
+            // then
+            assert_eq!(
+                content_path.map_err(|_| ()),
+                expected.map(ToString::to_string),
+                "path: {path}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_content_path_with_escaped_names() {
+        let test_cases = vec![
+            (
+                "/project/jcr_root/content/_jcr_content",
+                "/content/jcr:content",
+            ),
+            (
+                "/project/jcr_root/apps/_myco_tools/.content.xml",
+                "/apps/myco:tools",
+            ),
+            (
+                "/project/jcr_root/apps/x/sitemap.xml.txt",
+                "/apps/x/sitemap.xml.txt",
+            ),
+            ("/project/jcr_root/apps/x/file.txt.dir", "/apps/x/file.txt"),
+        ];
+
+        for (path, expected) in test_cases {
+            assert_eq!(
+                Path::new(path).content(),
+                Ok(expected.into()),
+                "path: {path}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_after_root_keeps_platform_names() {
+        // given
+        let path = Path::new("/project/jcr_root/content/_jcr_content/.content.xml");
+
+        // when
+        let path = path.after_root();
+
+        // then
+        assert_eq!(
+            path,
+            Ok("jcr_root/content/_jcr_content/.content.xml".into())
+        );
+    }
+
+    #[test]
+    fn test_jcr_path() {
+        let test_cases = vec![
+            ("/content/site/en", Ok("/content/site/en".to_string())),
+            ("/", Ok("/".to_string())),
+            (
+                "/project/jcr_root/content/_jcr_content",
+                Ok("/content/jcr:content".to_string()),
+            ),
+            ("project/jcr_root/apps", Ok("/apps".to_string())),
+            ("content/site", Err(NoJcrRoot("content/site".into()))),
+        ];
+
+        for (path, expected) in test_cases {
+            assert_eq!(Path::new(path).jcr_path(), expected, "path: {path}");
+        }
     }
 
     #[test]
@@ -92,17 +243,22 @@ mod test {
         let path = path.after_root();
 
         // then
-        assert_eq!(path, "jcr_root/content/abc");
+        assert_eq!(path, Ok("jcr_root/content/abc".into()));
     }
 
     #[test]
-    #[should_panic]
     fn test_from_root_with_wrong_path() {
         // given
         let path = Path::new("/home/zbychu/project/test");
 
-        // should panic
-        path.after_root();
+        // when
+        let res = path.after_root();
+
+        // then
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "path '/home/zbychu/project/test' is not inside of a jcr_root directory"
+        );
     }
 
     #[test]
@@ -135,17 +291,31 @@ mod test {
         let path = path.parent_from_root();
 
         // then
-        assert_eq!(path, "jcr_root/content");
+        assert_eq!(path, Ok("jcr_root/content".into()));
     }
 
     #[test]
-    #[should_panic]
     fn test_parent_on_root() {
         // given
         let root = OsPath::new("/");
         let path = Path::new(root.display().to_string());
 
-        // should_panic
-        path.parent_from_root();
+        // when
+        let res = path.parent_from_root();
+
+        // then
+        assert_eq!(res, Err(NoJcrRoot("/".into())));
+    }
+
+    #[test]
+    fn test_parent_on_jcr_root_dir() {
+        // given
+        let path = Path::new("/home/zbychu/jcr_root");
+
+        // when
+        let res = path.parent_from_root();
+
+        // then
+        assert_eq!(res, Err(NoJcrRoot("/home/zbychu/jcr_root".into())));
     }
 }
@@ -1,15 +1,19 @@
-use crate::cfg::Bundle;
+use crate::cfg::{Bundle, FilterMode, PathFilter, PkgSettings};
 use crate::path::Path;
 use anyhow::Result;
 use log::{debug, info};
-use std::convert::Into;
+use regex::Regex;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
 use std::fs::{create_dir_all, remove_dir_all, File};
+use std::hash::{BuildHasher, Hasher};
 use std::io::prelude::*;
 use std::path::PathBuf;
+use std::process;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tempfile::TempDir;
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub(crate) struct Pkg {
     name: String,
     version: String,
@@ -17,6 +21,53 @@ pub(crate) struct Pkg {
 }
 
 impl Pkg {
+    #[cfg(test)]
+    pub(crate) fn new<S: Into<String>>(group: S, name: S, version: S) -> Self {
+        Self {
+            name: name.into(),
+            version: version.into(),
+            group: group.into(),
+        }
+    }
+
+    /// Package uploading or downloading content, deleted right after it's used.
+    pub(crate) fn temporary(settings: &PkgSettings) -> Self {
+        Self::unique(settings, "pkg")
+    }
+
+    /// Package with remote content saved before `put`, see `snapshot` module.
+    pub(crate) fn snapshot(settings: &PkgSettings) -> Self {
+        Self::unique(settings, "snapshot")
+    }
+
+    fn unique(settings: &PkgSettings, purpose: &str) -> Self {
+        Self {
+            name: format!("{}-{}", settings.name_prefix(), purpose),
+            version: unique_version(),
+            group: settings.group().clone(),
+        }
+    }
+
+    /// Time (in seconds since epoch) the package was created at, when it's a temporary package
+    /// or snapshot created by `je` with given settings; other packages (even in the same group)
+    /// give `None`.
+    pub(crate) fn created_by_je(settings: &PkgSettings, name: &str, version: &str) -> Option<u64> {
+        let prefix = settings.name_prefix();
+        let pattern = if name == format!("{}-pkg", prefix) {
+            // versions of older `je` were plain seconds
+            r"^(\d{10})(-[0-9a-f]{6})?$"
+        } else if name == format!("{}-snapshot", prefix) {
+            r"^(\d{10})-[0-9a-f]{6}$"
+        } else {
+            return None;
+        };
+        let captures = Regex::new(pattern)
+            .expect("incorrect version regex")
+            .captures(version)?;
+        captures[1].parse().ok()
+    }
+
+    /// Path of the package under `/etc/packages`.
     pub(crate) fn path(&self) -> String {
         format!("{}/{}-{}.zip", self.group, self.name, self.version)
     }
@@ -24,24 +75,54 @@ impl Pkg {
 
 impl Default for Pkg {
     fn default() -> Self {
-        Self {
-            name: "je-pkg".into(),
-            version: SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("failed to count secs from EPOCH")
-                .as_secs()
-                .to_string(),
-            group: "je".into(),
-        }
+        Self::temporary(&PkgSettings::default())
     }
 }
 
+/// Seconds since epoch followed by a random suffix, so packages of runs started
+/// in the same second don't collide.
+fn unique_version() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("failed to count secs from EPOCH");
+    // each `RandomState` is seeded with different keys, even within one process
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(now.as_nanos());
+    hasher.write_u32(process::id());
+    format!("{}-{:06x}", now.as_secs(), hasher.finish() & 0xff_ffff)
+}
+
 pub(crate) fn mksimple(path: &Path, pkg: &Pkg) -> Result<TempDir> {
     debug!("creating pkg dir");
     let tmp_dir = TempDir::new()?;
     mk_jcr_root_dir(&tmp_dir)?;
     mk_vault_dir(&tmp_dir)?;
-    write_filter_content(&tmp_dir, &[path.content()])?;
+    write_filter_content(&tmp_dir, &[(path.content()?, PathFilter::default())])?;
+    write_properties_content(&tmp_dir, pkg)?;
+    Ok(tmp_dir)
+}
+
+/// Creates package dir with a filter covering only the node itself, without its descendants.
+pub(crate) fn mknode(jcr_path: &str, pkg: &Pkg) -> Result<TempDir> {
+    debug!("creating pkg dir for node {}", jcr_path);
+    let tmp_dir = TempDir::new()?;
+    mk_jcr_root_dir(&tmp_dir)?;
+    mk_vault_dir(&tmp_dir)?;
+    let filter = PathFilter::new(None, vec![&regex::escape(jcr_path)], vec![]);
+    write_filter_content(&tmp_dir, &[(jcr_path.to_string(), filter)])?;
+    write_properties_content(&tmp_dir, pkg)?;
+    Ok(tmp_dir)
+}
+
+/// Creates package dir with empty `jcr_root` and a `replace` filter covering the path, so
+/// installing the package deletes the node with all its descendants.
+pub(crate) fn mkdelete(jcr_path: &str, pkg: &Pkg) -> Result<TempDir> {
+    debug!("creating pkg dir deleting {}", jcr_path);
+    let tmp_dir = TempDir::new()?;
+    mk_jcr_root_dir(&tmp_dir)?;
+    mk_vault_dir(&tmp_dir)?;
+    let filter = PathFilter::new(Some(FilterMode::Replace), vec![], vec![]);
+    write_filter_content(&tmp_dir, &[(jcr_path.to_string(), filter)])?;
     write_properties_content(&tmp_dir, pkg)?;
     Ok(tmp_dir)
 }
@@ -63,10 +144,11 @@ fn vault_path(tmp_dir: &TempDir) -> PathBuf {
     tmp_dir.path().join("META-INF/vault")
 }
 
-fn write_filter_content(tmp_dir: &TempDir, content_paths: &[String]) -> Result<()> {
+/// Writes `filter.xml` with a filter for each pair of JCR path and its filter settings.
+fn write_filter_content(tmp_dir: &TempDir, filters: &[(String, PathFilter)]) -> Result<()> {
     let filter_path = format!("{}/filter.xml", vault_path(tmp_dir).display());
     let mut filter_file = File::create(&filter_path)?;
-    let filter_content = filter_content(content_paths);
+    let filter_content = filter_content(filters);
     debug!(
         "writing content\n{}\nto filter {}",
         filter_content, filter_path
@@ -75,7 +157,7 @@ fn write_filter_content(tmp_dir: &TempDir, content_paths: &[String]) -> Result<(
     Ok(())
 }
 
-fn filter_content(paths: &[String]) -> String {
+fn filter_content(filters: &[(String, PathFilter)]) -> String {
     let filter_prefix = r#"<?xml version="1.0" encoding="UTF-8"?>
 <workspaceFilter version="1.0">"#;
     let filter_postfix = "</workspaceFilter>";
@@ -85,39 +167,50 @@ fn filter_content(paths: &[String]) -> String {
 {}
 "#,
         filter_prefix,
-        write_filters(paths),
+        write_filters(filters),
         filter_postfix,
     )
 }
 
-fn write_filters(paths: &[String]) -> String {
-    paths
+fn write_filters(filters: &[(String, PathFilter)]) -> String {
+    filters
         .iter()
-        .map(normalize)
-        .map(create_filter)
+        .map(|(path, filter)| create_filter(path, filter))
         .collect::<Vec<String>>()
         .join("\n    ")
 }
 
-fn normalize<S: Into<String>>(path: S) -> String {
-    path.into()
-        .replace("_jcr_", "jcr:")
-        .replace("_rep_", "rep:")
-        .replace("_oak_", "oak:")
-        .replace("_sling_", "sling:")
-        .replace("_granite_", "granite:")
-        .replace("_cq_", "cq:")
-        .replace("_dam_", "dam:")
-        .replace("_exif_", "exif:")
-        .replace("_social_", "social:")
-        .replace(".content.xml", "")
-        .replace(".xml", "")
-        // windows
-        .replace('\\', "/")
+fn create_filter<S: AsRef<str>>(path: S, filter: &PathFilter) -> String {
+    let mode = filter
+        .mode()
+        .map(|m| format!(r#" mode="{}""#, m.as_str()))
+        .unwrap_or_default();
+    let root = format!(r#"<filter root="{}"{}"#, escape_attr(path.as_ref()), mode);
+    if !filter.has_rules() {
+        return format!("{root}/>");
+    }
+    // includes go first so excludes take precedence (the last matching rule wins)
+    let rules = filter
+        .include()
+        .iter()
+        .map(|p| format!(r#"<include pattern="{}"/>"#, escape_attr(p)))
+        .chain(
+            filter
+                .exclude()
+                .iter()
+                .map(|p| format!(r#"<exclude pattern="{}"/>"#, escape_attr(p))),
+        )
+        .map(|rule| format!("\n        {rule}"))
+        .collect::<String>();
+    format!("{root}>{rules}\n    </filter>")
 }
 
-fn create_filter<S: Into<String>>(path: S) -> String {
-    format!(r#"<filter root="{}"/>"#, path.into())
+fn escape_attr(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
 }
 
 fn write_properties_content(tmp_dir: &TempDir, pkg: &Pkg) -> Result<()> {
@@ -150,7 +243,12 @@ pub(crate) fn mkbundle(bundle: &Bundle, pkg: &Pkg) -> Result<TempDir> {
     let tmp_dir = TempDir::new()?;
     mk_jcr_root_dir(&tmp_dir)?;
     mk_vault_dir(&tmp_dir)?;
-    write_filter_content(&tmp_dir, bundle.paths())?;
+    let filters = bundle
+        .paths()
+        .iter()
+        .map(|p| Ok((Path::new(p.path()).content()?, p.filter().clone())))
+        .collect::<Result<Vec<(String, PathFilter)>>>()?;
+    write_filter_content(&tmp_dir, &filters)?;
     write_properties_content(&tmp_dir, pkg)?;
     Ok(tmp_dir)
 }
@@ -167,7 +265,7 @@ mod test {
     use super::*;
     use anyhow::Result;
     use std::fs::create_dir_all;
-    use std::fs::read_to_string;
+    use std::fs::{read_dir, read_to_string};
     use std::path::Path as OsPath;
     use tempfile::TempDir;
 
@@ -203,6 +301,81 @@ mod test {
         assert_eq!(path, "group-name/-.zip");
     }
 
+    #[test]
+    fn test_temporary_with_custom_settings() {
+        // given
+        let settings = PkgSettings::new("my-team", "alice");
+
+        // when
+        let temporary = Pkg::temporary(&settings);
+        let snapshot = Pkg::snapshot(&settings);
+
+        // then
+        assert_eq!(temporary.group, "my-team");
+        assert_eq!(temporary.name, "alice-pkg");
+        assert_eq!(snapshot.name, "alice-snapshot");
+        assert_ne!(temporary.version, snapshot.version);
+        let created = Pkg::created_by_je(&settings, &temporary.name, &temporary.version).unwrap();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        assert!(now.as_secs() - created < 60);
+    }
+
+    #[test]
+    fn test_unique_version() {
+        // given
+        let pattern = regex::Regex::new(r"^\d{10}-[0-9a-f]{6}$").unwrap();
+
+        // when
+        // suffixes are random, so few versions are generated to keep a collision unlikely
+        let versions: Vec<String> = (0..10).map(|_| unique_version()).collect();
+
+        // then
+        assert!(versions.iter().all(|v| pattern.is_match(v)));
+        let mut distinct = versions.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), versions.len());
+    }
+
+    #[test]
+    fn test_created_by_je() {
+        // given
+        let settings = PkgSettings::default();
+        let pkgs = [
+            ("je-pkg", "1700000000-0a1b2c"),
+            ("je-snapshot", "1700000000-0a1b2c"),
+            ("je-pkg", "1700000000"),
+            ("je-snapshot", "1700000000"),
+            ("je-pkg", "42"),
+            ("je-pkg", "1.0.0"),
+            ("je-pkg-old", "1700000000-0a1b2c"),
+            ("jenkins-build", "42"),
+            ("jenkins-build", "1700000000-0a1b2c"),
+        ];
+
+        // when
+        let created: Vec<_> = pkgs
+            .iter()
+            .map(|(name, version)| Pkg::created_by_je(&settings, name, version))
+            .collect();
+
+        // then
+        assert_eq!(
+            created,
+            vec![
+                Some(1_700_000_000),
+                Some(1_700_000_000),
+                Some(1_700_000_000),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None
+            ]
+        );
+    }
+
     #[test]
     fn test_mk_jcr_root_dir() -> Result<()> {
         // given
@@ -236,7 +409,7 @@ mod test {
         create_dir_all(format!("{}/META-INF/vault", tmp_dir.path().display()))?;
 
         // when
-        write_filter_content(&tmp_dir, &["/content/path".into()])?;
+        write_filter_content(&tmp_dir, &[("/content/path".into(), PathFilter::default())])?;
 
         // then
         assert!(OsPath::new(&format!(
@@ -333,52 +506,162 @@ mod test {
     }
 
     #[test]
-    fn test_normalize() {
-        // given
+    fn test_create_filter() {
         let test_cases = vec![
-            ("/content/_jcr_content", "/content/jcr:content"),
-            ("/content/_rep_policy", "/content/rep:policy"),
-            ("/content/_oak_root", "/content/oak:root"),
-            ("/content/_sling_order", "/content/sling:order"),
-            ("/content/_granite_var", "/content/granite:var"),
-            ("/content/_cq_dialog", "/content/cq:dialog"),
-            ("/content/_dam_asset", "/content/dam:asset"),
-            ("/content/_exif_fi", "/content/exif:fi"),
-            ("/content/_social_media", "/content/social:media"),
             (
-                "/content/_jcr_content/.content.xml",
-                "/content/jcr:content/",
+                "/content/jcr:content",
+                r#"<filter root="/content/jcr:content"/>"#,
             ),
-            ("/content/_jcr_content.xml", "/content/jcr:content"),
-            // Windows
-            ("\\content\\_jcr_content", "/content/jcr:content"),
-            ("\\content\\_rep_policy", "/content/rep:policy"),
-            ("\\content\\_oak_root", "/content/oak:root"),
-            ("\\content\\_sling_order", "/content/sling:order"),
-            ("\\content\\_granite_var", "/content/granite:var"),
-            ("\\content\\_cq_dialog", "/content/cq:dialog"),
-            ("\\content\\_dam_asset", "/content/dam:asset"),
-            ("\\content\\_exif_fi", "/content/exif:fi"),
-            ("\\content\\_social_media", "/content/social:media"),
+            ("/content/a&b", r#"<filter root="/content/a&amp;b"/>"#),
             (
-                "\\content\\_jcr_content\\.content.xml",
-                "/content/jcr:content/",
+                "/content/\"q\"<>",
+                r#"<filter root="/content/&quot;q&quot;&lt;&gt;"/>"#,
             ),
-            ("\\content\\_jcr_content.xml", "/content/jcr:content"),
         ];
 
-        // then
-        for (input, expected) in test_cases {
-            assert_eq!(normalize(input), expected);
+        for (path, expected) in test_cases {
+            assert_eq!(create_filter(path, &PathFilter::default()), expected);
         }
     }
 
+    #[test]
+    fn test_create_filter_with_mode_and_rules() {
+        // given
+        let merge_only = PathFilter::new(Some(FilterMode::Merge), vec![], vec![]);
+        let with_rules = PathFilter::new(
+            Some(FilterMode::Update),
+            vec!["/content/dam/site/.*"],
+            vec![".*/renditions(/.*)?", ".*<&>.*"],
+        );
+
+        // then
+        assert_eq!(
+            create_filter("/content/site", &merge_only),
+            r#"<filter root="/content/site" mode="merge"/>"#
+        );
+        assert_eq!(
+            create_filter("/content/dam/site", &with_rules),
+            r#"<filter root="/content/dam/site" mode="update">
+        <include pattern="/content/dam/site/.*"/>
+        <exclude pattern=".*/renditions(/.*)?"/>
+        <exclude pattern=".*&lt;&amp;&gt;.*"/>
+    </filter>"#
+        );
+    }
+
+    #[test]
+    fn test_mk_bundle_with_filters() -> Result<()> {
+        // given
+        let bundle: Bundle = toml::from_str(
+            r#"
+            name = "dam"
+            paths = [
+                "/project/jcr_root/content/site",
+                { path = "/project/jcr_root/content/dam/site", mode = "replace", exclude = [".*/renditions(/.*)?"] },
+            ]
+        "#,
+        )?;
+        let pkg = Pkg::default();
+
+        // when
+        let tmp_dir_path = mkbundle(&bundle, &pkg)?;
+
+        // then
+        let filter_contents =
+            read_to_string(tmp_dir_path.path().join("META-INF/vault/filter.xml"))?;
+        assert_eq!(
+            filter_contents,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<workspaceFilter version="1.0">
+    <filter root="/content/site"/>
+    <filter root="/content/dam/site" mode="replace">
+        <exclude pattern=".*/renditions(/.*)?"/>
+    </filter>
+</workspaceFilter>
+"#,
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_mk_simple_dir_with_escaped_path() -> Result<()> {
+        // given
+        let file_path = Path::new("/home/user/project/jcr_root/apps/_myco_x/sitemap.xml.txt");
+        let pkg = Pkg::default();
+
+        // when
+        let tmp_dir_path = mksimple(&file_path, &pkg)?;
+
+        // then
+        let filter_contents =
+            read_to_string(tmp_dir_path.path().join("META-INF/vault/filter.xml"))?;
+        assert_eq!(
+            filter_contents,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<workspaceFilter version="1.0">
+    <filter root="/apps/myco:x/sitemap.xml.txt"/>
+</workspaceFilter>
+"#,
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_mk_node() -> Result<()> {
+        // given
+        let pkg = Pkg::default();
+
+        // when
+        let tmp_dir_path = mknode("/content/site/jcr:content", &pkg)?;
+
+        // then
+        let filter_contents =
+            read_to_string(tmp_dir_path.path().join("META-INF/vault/filter.xml"))?;
+        assert_eq!(
+            filter_contents,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<workspaceFilter version="1.0">
+    <filter root="/content/site/jcr:content">
+        <include pattern="/content/site/jcr:content"/>
+    </filter>
+</workspaceFilter>
+"#,
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_mk_delete() -> Result<()> {
+        // given
+        let pkg = Pkg::default();
+
+        // when
+        let tmp_dir_path = mkdelete("/content/site/old", &pkg)?;
+
+        // then
+        let filter_contents =
+            read_to_string(tmp_dir_path.path().join("META-INF/vault/filter.xml"))?;
+        assert_eq!(
+            filter_contents,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<workspaceFilter version="1.0">
+    <filter root="/content/site/old" mode="replace"/>
+</workspaceFilter>
+"#,
+        );
+        assert_eq!(read_dir(tmp_dir_path.path().join("jcr_root"))?.count(), 0);
+        Ok(())
+    }
+
     #[test]
     fn test_mk_bundle() -> Result<()> {
         // given
         let bundle = Bundle::new(
             "NOT-IMPORTANT",
-            vec!["/content/client", "/content/dam/test"],
+            vec![
+                "/home/user/project/jcr_root/content/client",
+                "/home/user/project/jcr_root/content/dam/test",
+            ],
         );
         let pkg = Pkg::default();
 
@@ -1,18 +1,161 @@
-use crate::http::Client;
+use crate::cfg::BuildWait;
+use crate::http::{Client, Response};
 use crate::pkgdir;
-use anyhow::Result;
+use crate::progress;
+use anyhow::{bail, Context, Result};
+use getset::Getters;
 use log::{debug, info};
+use regex::Regex;
+use serde_derive::Deserialize;
+use std::cmp::min;
 use std::fs::File;
-use std::io::prelude::*;
+use std::io::{prelude::*, BufWriter};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
 use tempfile::TempDir;
 
+/// Body returned by each command of `/crx/packmgr/service/.json`.
+#[derive(Debug, Deserialize)]
+struct ServiceResponse {
+    success: bool,
+
+    #[serde(default)]
+    msg: String,
+
+    /// path of the uploaded package
+    path: Option<String>,
+}
+
+impl ServiceResponse {
+    fn check<S: AsRef<str>>(resp: Response, action: S) -> Result<Self> {
+        let action = action.as_ref();
+        let status = resp.status();
+        let body = resp.text()?;
+        let parsed = serde_json::from_str::<ServiceResponse>(&body);
+        if let Some(status) = status.filter(|s| !s.is_success()) {
+            let reason = match parsed {
+                Ok(parsed) => format!("{} ({})", parsed.msg, status),
+                Err(_) => format!("server responded with {status}"),
+            };
+            bail!("failed to {}: {}", action, reason);
+        }
+        let parsed =
+            parsed.with_context(|| format!("failed to parse {action} response: '{body}'"))?;
+        if !parsed.success {
+            bail!("failed to {}: {}", action, parsed.msg);
+        }
+        debug!("{}: {}", action, parsed.msg);
+        Ok(parsed)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PkgList {
+    results: Vec<PkgInfo>,
+}
+
+/// Package as listed by `/crx/packmgr/list.jsp`.
+#[derive(Debug, Getters, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PkgInfo {
+    #[serde(default)]
+    #[getset(get = "pub")]
+    path: String,
+
+    #[serde(default)]
+    #[getset(get = "pub")]
+    group: String,
+
+    #[serde(default)]
+    #[getset(get = "pub")]
+    name: String,
+
+    #[serde(default)]
+    #[getset(get = "pub")]
+    version: String,
+
+    #[serde(default)]
+    build_count: u32,
+
+    last_wrapped: Option<u64>,
+
+    last_unpacked: Option<u64>,
+}
+
+impl PkgInfo {
+    fn is_built(&self) -> bool {
+        self.build_count > 0 || self.last_wrapped.is_some()
+    }
+
+    pub(crate) fn is_installed(&self) -> bool {
+        self.last_unpacked.is_some()
+    }
+
+    /// Package id in `group:name:version` form.
+    pub(crate) fn pid(&self) -> String {
+        format!("{}:{}:{}", self.group, self.name, self.version)
+    }
+
+    /// Path of the package zip under `/etc/packages`, as it's stored on the instance; it can
+    /// differ from `group/name-version.zip`, e.g. when the package was uploaded under other name.
+    pub(crate) fn rel_path(&self) -> &str {
+        rel_path(&self.path)
+    }
+
+    /// Tells if `reference` is the id of the package or its path, with or without
+    /// `/etc/packages/` prefix.
+    fn is_referred_by(&self, reference: &str) -> bool {
+        self.pid() == reference || self.rel_path() == rel_path(reference)
+    }
+}
+
+fn rel_path(path: &str) -> &str {
+    path.trim_start_matches(PACKAGES_ROOT)
+        .trim_start_matches('/')
+}
+
+const PACKAGES_ROOT: &str = "/etc/packages";
+
+/// Lists all packages available on the instance.
+pub(crate) fn list_pkgs(client: &impl Client) -> Result<Vec<PkgInfo>> {
+    let resp = client.get("/crx/packmgr/list.jsp")?;
+    debug!("list pkgs response: {:#?}", resp);
+    let list: PkgList = resp
+        .error_for_status()
+        .and_then(Response::json)
+        .context("failed to list packages")?;
+    Ok(list.results)
+}
+
+/// Finds the package by its id (`group:name:version`) or path (e.g. `my_packages/site-1.0.zip`).
+pub(crate) fn find_pkg(client: &impl Client, reference: &str) -> Result<PkgInfo> {
+    list_pkgs(client)?
+        .into_iter()
+        .find(|info| info.is_referred_by(reference))
+        .with_context(|| format!("package {} not found on the instance", reference))
+}
+
 pub(crate) fn upload_pkg(client: &impl Client, dir: &TempDir) -> Result<()> {
+    upload_file(client, dir.path().join("pkg.zip"), false)?;
+    Ok(())
+}
+
+/// Uploads the package zip, replacing the existing one when `force` is set;
+/// returns path of the package on the instance.
+pub(crate) fn upload_file<A: AsRef<Path>>(
+    client: &impl Client,
+    file: A,
+    force: bool,
+) -> Result<String> {
+    let force = if force { "&force=true" } else { "" };
     let resp = client.post_file(
-        "/crx/packmgr/service/.json?cmd=upload",
-        dir.path().join("pkg.zip"),
+        format!("/crx/packmgr/service/.json?cmd=upload{}", force),
+        file,
     )?;
     debug!("upload pkg response: {:#?}", resp);
-    Ok(())
+    let parsed = ServiceResponse::check(resp, "upload package")?;
+    Ok(parsed.path.unwrap_or_default())
 }
 
 pub(crate) fn build_pkg(client: &impl Client, pkg: &pkgdir::Pkg) -> Result<()> {
@@ -21,54 +164,171 @@ pub(crate) fn build_pkg(client: &impl Client, pkg: &pkgdir::Pkg) -> Result<()> {
         pkg.path(),
     ))?;
     debug!("build pkg response: {:#?}", resp);
+    ServiceResponse::check(resp, format!("build package {}", pkg.path()))?;
     Ok(())
 }
 
+pub(crate) fn wait_for_build(
+    client: &impl Client,
+    pkg: &pkgdir::Pkg,
+    wait: &BuildWait,
+) -> Result<()> {
+    info!("waiting for pkg to be built");
+    let timeout = Duration::from_secs(wait.timeout_secs());
+    let max_interval = Duration::from_millis(wait.max_interval_ms());
+    let mut interval = Duration::from_millis(wait.interval_ms());
+    let start = Instant::now();
+    loop {
+        if is_built(client, pkg)? {
+            debug!("pkg built after {:?}", start.elapsed());
+            return Ok(());
+        }
+        if start.elapsed() >= timeout {
+            bail!(
+                "package {} was not built within {}s",
+                pkg.path(),
+                wait.timeout_secs()
+            );
+        }
+        debug!("pkg not built yet, checking again in {:?}", interval);
+        thread::sleep(interval);
+        interval = min(interval * 2, max_interval);
+    }
+}
+
+fn is_built(client: &impl Client, pkg: &pkgdir::Pkg) -> Result<bool> {
+    let resp = client.get(format!(
+        "/crx/packmgr/list.jsp?path=/etc/packages/{}",
+        pkg.path()
+    ))?;
+    debug!("pkg status response: {:#?}", resp);
+    let list: PkgList = resp
+        .error_for_status()
+        .and_then(Response::json)
+        .context("failed to check package status")?;
+    Ok(list.results.iter().any(PkgInfo::is_built))
+}
+
 pub(crate) fn download_pkg(client: &impl Client, dir: &TempDir, pkg: &pkgdir::Pkg) -> Result<()> {
+    download_to(client, &pkg.path(), dir.path().join("res.zip"))
+}
+
+/// Streams the package to the `target` file.
+pub(crate) fn download_to<A: AsRef<Path>>(
+    client: &impl Client,
+    pkg_path: &str,
+    target: A,
+) -> Result<()> {
     info!("downloading pkg");
-    let resp = client.get(format!("/etc/packages/{}", pkg.path(),))?;
+    let resp = client.get(format!("/etc/packages/{}", pkg_path))?;
     debug!("download pkg response: {:#?}", resp);
-    let mut pkg_file = File::create(dir.path().join("res.zip"))?;
-    pkg_file.write_all(&resp.bytes()?)?;
-    Ok(())
+    let resp = resp
+        .error_for_status()
+        .with_context(|| format!("failed to download package {}", pkg_path))?;
+    let expected = resp.content_length();
+    let bar = progress::transfer(expected, "downloading");
+    let mut pkg_file = bar.wrap_write(BufWriter::new(File::create(target)?));
+    let written = resp
+        .copy_to(&mut pkg_file)
+        .with_context(|| format!("failed to download package {}", pkg_path))?;
+    pkg_file.flush()?;
+    bar.finish_and_clear();
+    match expected {
+        Some(expected) if expected != written => bail!(
+            "package {} is incomplete: downloaded {} of {} bytes",
+            pkg_path,
+            written,
+            expected
+        ),
+        _ => Ok(()),
+    }
 }
 
-pub(crate) fn install_pkg(client: &impl Client, pkg: &pkgdir::Pkg) -> Result<()> {
+pub(crate) fn install_pkg(client: &impl Client, pkg_path: &str) -> Result<()> {
     let resp = client.post(format!(
         "/crx/packmgr/service/.json/etc/packages/{}?cmd=install",
-        pkg.path()
+        pkg_path
     ))?;
     debug!("install pkg response: {:#?}", resp);
+    ServiceResponse::check(resp, format!("install package {}", pkg_path))?;
+    Ok(())
+}
+
+/// Runs the installation in dry-run mode and returns lines of the installation log describing
+/// changes, e.g. `A /content/site/page` (added), `U ...` (updated), `D ...` (deleted)
+/// or `E ...` (error).
+pub(crate) fn dry_run_pkg(client: &impl Client, pkg_path: &str) -> Result<Vec<String>> {
+    let resp = client.post(format!(
+        "/crx/packmgr/service/script.html/etc/packages/{}?cmd=dryrun",
+        pkg_path
+    ))?;
+    debug!("dry run pkg response: {:#?}", resp);
+    let log = resp
+        .error_for_status()
+        .and_then(Response::text)
+        .context(format!(
+            "failed to dry run installation of package {}",
+            pkg_path
+        ))?;
+    Ok(parse_install_log(&log))
+}
+
+fn parse_install_log(log: &str) -> Vec<String> {
+    let tag = Regex::new("<[^>]*>").expect("incorrect tag regex");
+    let change = Regex::new(r"^[AUDE!] /").expect("incorrect change regex");
+    log.split("<br>")
+        .flat_map(str::lines)
+        .map(|line| {
+            tag.replace_all(line, "")
+                .replace("&nbsp;", " ")
+                .replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&quot;", "\"")
+                .replace("&amp;", "&")
+                .trim()
+                .to_string()
+        })
+        .filter(|line| change.is_match(line))
+        .collect()
+}
+
+pub(crate) fn uninstall_pkg(client: &impl Client, pkg_path: &str) -> Result<()> {
+    let resp = client.post(format!(
+        "/crx/packmgr/service/.json/etc/packages/{}?cmd=uninstall",
+        pkg_path
+    ))?;
+    debug!("uninstall pkg response: {:#?}", resp);
+    ServiceResponse::check(resp, format!("uninstall package {}", pkg_path))?;
     Ok(())
 }
 
-pub(crate) fn delete_pkg(client: &impl Client, debug: bool, pkg: &pkgdir::Pkg) -> Result<()> {
+pub(crate) fn delete_pkg(client: &impl Client, debug: bool, pkg_path: &str) -> Result<()> {
     if debug {
         info!("package deletion omitted because of passed flag");
         return Ok(());
     }
     let resp = client.post(format!(
         "/crx/packmgr/service/.json/etc/packages/{}?cmd=delete",
-        pkg.path()
+        pkg_path
     ))?;
     debug!("delete pkg response: {:#?}", resp);
+    ServiceResponse::check(resp, format!("delete package {}", pkg_path))?;
     Ok(())
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::http::Response;
     use crate::pkgdir::Pkg;
+    use crate::testutils::ClientSpy;
     use anyhow::Result;
     use regex::Regex;
-    use std::cell::RefCell;
     use std::path::Path;
 
     #[test]
     fn test_upload_pkg() -> Result<()> {
         // given
-        let spy = ClientSpy::new();
+        let spy = ClientSpy::with_responses(&[(200, SUCCESS)]);
         let dir = TempDir::new()?;
         let pkg_path = dir.path().join("pkg.zip");
 
@@ -86,13 +346,31 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_upload_pkg_when_unauthorized() -> Result<()> {
+        // given
+        let spy = ClientSpy::with_responses(&[(401, "<html>Unauthorized</html>")]);
+        let dir = TempDir::new()?;
+
+        // when
+        let res = upload_pkg(&spy, &dir);
+
+        // then
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "failed to upload package: server responded with 401 Unauthorized"
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_build_pkg() -> Result<()> {
         // given
-        let spy = ClientSpy::new();
+        let spy = ClientSpy::with_responses(&[(200, r#"{"success":true,"msg":"Package built"}"#)]);
         let pkg = Pkg::default();
-        let req_regex =
-            Regex::new(r"/crx/packmgr/service/\.json/etc/packages/je/je-pkg-\d+\.zip\?cmd=build")?;
+        let req_regex = Regex::new(
+            r"/crx/packmgr/service/\.json/etc/packages/je/je-pkg-\d+-[0-9a-f]{6}\.zip\?cmd=build",
+        )?;
 
         // when
         build_pkg(&spy, &pkg)?;
@@ -102,13 +380,71 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_build_pkg_when_build_failed() {
+        // given
+        let spy =
+            ClientSpy::with_responses(&[(200, r#"{"success":false,"msg":"Repository error"}"#)]);
+        let pkg = Pkg::default();
+
+        // when
+        let res = build_pkg(&spy, &pkg);
+
+        // then
+        let err = res.unwrap_err().to_string();
+        assert!(err.contains("failed to build package"));
+        assert!(err.contains("Repository error"));
+    }
+
+    #[test]
+    fn test_wait_for_build_when_pkg_built() -> Result<()> {
+        // given
+        let spy = ClientSpy::with_responses(&[
+            (200, r#"{"results":[{"buildCount":0}],"total":1}"#),
+            (200, r#"{"results":[],"total":0}"#),
+            (
+                200,
+                r#"{"results":[{"buildCount":1,"lastWrapped":1600000000000}],"total":1}"#,
+            ),
+        ]);
+        let pkg = Pkg::default();
+        let req_regex = Regex::new(
+            r"/crx/packmgr/list\.jsp\?path=/etc/packages/je/je-pkg-\d+-[0-9a-f]{6}\.zip",
+        )?;
+
+        // when
+        wait_for_build(&spy, &pkg, &BuildWait::new(60, 0, 0))?;
+
+        // then
+        assert!(req_regex.is_match(&spy.get_req()));
+        assert_eq!(spy.remaining_responses(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_wait_for_build_when_timed_out() {
+        // given
+        let spy =
+            ClientSpy::with_responses(&[(200, r#"{"results":[{"buildCount":0}],"total":1}"#)]);
+        let pkg = Pkg::default();
+
+        // when
+        let res = wait_for_build(&spy, &pkg, &BuildWait::new(0, 0, 0));
+
+        // then
+        assert!(res
+            .unwrap_err()
+            .to_string()
+            .contains("was not built within 0s"));
+    }
+
     #[test]
     fn test_download_pkg() -> Result<()> {
         // given
-        let spy = ClientSpy::new();
+        let spy = ClientSpy::with_responses(&[(200, "zipped package")]);
         let pkg = Pkg::default();
         let dir = TempDir::new()?;
-        let req_regex = Regex::new(r"/etc/packages/je/je-pkg-\d+\.zip")?;
+        let req_regex = Regex::new(r"/etc/packages/je/je-pkg-\d+-[0-9a-f]{6}\.zip")?;
 
         // when
         download_pkg(&spy, &dir, &pkg)?;
@@ -116,6 +452,10 @@ mod test {
         // then
         println!("req: {}", spy.get_req());
         assert!(req_regex.is_match(&spy.get_req()));
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("res.zip"))?,
+            "zipped package"
+        );
 
         Ok(())
     }
@@ -123,14 +463,14 @@ mod test {
     #[test]
     fn test_install_pkg() -> Result<()> {
         // given
-        let spy = ClientSpy::new();
+        let spy = ClientSpy::with_responses(&[(200, SUCCESS)]);
         let pkg = Pkg::default();
         let req_regex = Regex::new(
-            r"/crx/packmgr/service/\.json/etc/packages/je/je-pkg-\d+\.zip\?cmd=install",
+            r"/crx/packmgr/service/\.json/etc/packages/je/je-pkg-\d+-[0-9a-f]{6}\.zip\?cmd=install",
         )?;
 
         // when
-        install_pkg(&spy, &pkg)?;
+        install_pkg(&spy, &pkg.path())?;
 
         // then
         assert!(req_regex.is_match(&spy.post_req()));
@@ -138,85 +478,290 @@ mod test {
     }
 
     #[test]
-    fn test_delete_when_deletion_turned_on_pkg() -> Result<()> {
+    fn test_dry_run_pkg() -> Result<()> {
         // given
-        let spy = ClientSpy::new();
+        let spy = ClientSpy::with_responses(&[(
+            200,
+            r#"<html><body><pre>
+Installing content (dry run)...<br>
+<span class="-"><b>-</b>&nbsp;/content</span><br>
+<span class="A"><b>A</b>&nbsp;/content/site/new (nt:unstructured)</span><br>
+<span class="U"><b>U</b>&nbsp;/content/site/jcr:content</span><br>
+<span class="D"><b>D</b>&nbsp;/content/site/old</span><br>
+<span class="E"><b>E</b>&nbsp;/content/site/broken (java.lang.Exception: &lt;oops&gt;)</span><br>
+Package installed in 42ms.<br>
+</pre></body></html>"#,
+        )]);
         let pkg = Pkg::default();
-        let req_regex =
-            Regex::new(r"/crx/packmgr/service/\.json/etc/packages/je/je-pkg-\d+\.zip\?cmd=delete")?;
+        let req_regex = Regex::new(
+            r"/crx/packmgr/service/script\.html/etc/packages/je/je-pkg-\d+-[0-9a-f]{6}\.zip\?cmd=dryrun",
+        )?;
 
         // when
-        delete_pkg(&spy, false, &pkg)?;
+        let changes = dry_run_pkg(&spy, &pkg.path())?;
 
         // then
         assert!(req_regex.is_match(&spy.post_req()));
+        assert_eq!(
+            changes,
+            vec![
+                "A /content/site/new (nt:unstructured)",
+                "U /content/site/jcr:content",
+                "D /content/site/old",
+                "E /content/site/broken (java.lang.Exception: <oops>)",
+            ]
+        );
         Ok(())
     }
 
     #[test]
-    fn test_delete_when_deletion_skipped() -> Result<()> {
+    fn test_dry_run_pkg_when_unauthorized() {
         // given
-        let spy = ClientSpy::new();
+        let spy = ClientSpy::with_responses(&[(401, "Unauthorized")]);
         let pkg = Pkg::default();
 
         // when
-        delete_pkg(&spy, true, &pkg)?;
+        let res = dry_run_pkg(&spy, &pkg.path());
 
         // then
-        assert_eq!(spy.post_req(), "");
+        assert!(res
+            .unwrap_err()
+            .to_string()
+            .starts_with("failed to dry run installation of package"));
+    }
+
+    #[test]
+    fn test_delete_when_deletion_turned_on_pkg() -> Result<()> {
+        // given
+        let spy = ClientSpy::with_responses(&[(200, SUCCESS)]);
+        let pkg = Pkg::default();
+        let req_regex = Regex::new(
+            r"/crx/packmgr/service/\.json/etc/packages/je/je-pkg-\d+-[0-9a-f]{6}\.zip\?cmd=delete",
+        )?;
+
+        // when
+        delete_pkg(&spy, false, &pkg.path())?;
+
+        // then
+        assert!(req_regex.is_match(&spy.post_req()));
         Ok(())
     }
 
-    fn to_string<A: AsRef<Path>>(path: A) -> String {
-        path.as_ref().display().to_string()
+    #[test]
+    fn test_install_pkg_when_install_failed() {
+        // given
+        let spy = ClientSpy::with_responses(&[(
+            200,
+            r#"{"success":false,"msg":"Package not found: /etc/packages/je/missing.zip"}"#,
+        )]);
+        let pkg = Pkg::default();
+
+        // when
+        let res = install_pkg(&spy, &pkg.path());
+
+        // then
+        let err = res.unwrap_err().to_string();
+        assert!(err.starts_with("failed to install package je/je-pkg-"));
+        assert!(err.ends_with("Package not found: /etc/packages/je/missing.zip"));
     }
 
-    struct ClientSpy {
-        post_file_req: RefCell<(String, String)>,
-        post_req: RefCell<String>,
-        get_req: RefCell<String>,
+    #[test]
+    fn test_install_pkg_when_response_is_not_json() {
+        // given
+        let spy = ClientSpy::with_responses(&[(200, "<html>Login</html>")]);
+        let pkg = Pkg::default();
+
+        // when
+        let res = install_pkg(&spy, &pkg.path());
+
+        // then
+        assert!(res
+            .unwrap_err()
+            .to_string()
+            .contains("failed to parse install package"));
     }
 
-    impl ClientSpy {
-        fn new() -> Self {
-            Self {
-                post_file_req: RefCell::new((String::new(), String::new())),
-                post_req: RefCell::new(String::new()),
-                get_req: RefCell::new(String::new()),
-            }
-        }
+    #[test]
+    fn test_delete_pkg_when_server_error() {
+        // given
+        let spy =
+            ClientSpy::with_responses(&[(500, r#"{"success":false,"msg":"Internal error"}"#)]);
+        let pkg = Pkg::default();
 
-        fn post_file_req(&self) -> (String, String) {
-            self.post_file_req.clone().take()
-        }
+        // when
+        let res = delete_pkg(&spy, false, &pkg.path());
 
-        fn post_req(&self) -> String {
-            self.post_req.clone().take()
-        }
+        // then
+        assert!(res
+            .unwrap_err()
+            .to_string()
+            .ends_with("Internal error (500 Internal Server Error)"));
+    }
 
-        fn get_req(&self) -> String {
-            self.get_req.clone().take()
-        }
+    #[test]
+    fn test_download_pkg_when_not_found() -> Result<()> {
+        // given
+        let spy = ClientSpy::with_responses(&[(404, "Not Found")]);
+        let pkg = Pkg::default();
+        let dir = TempDir::new()?;
+
+        // when
+        let res = download_pkg(&spy, &dir, &pkg);
+
+        // then
+        let err = res.unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("failed to download package je/je-pkg-"));
+        assert_eq!(
+            err.root_cause().to_string(),
+            "server responded with 404 Not Found"
+        );
+        assert!(!dir.path().join("res.zip").exists());
+        Ok(())
     }
 
-    impl Client for ClientSpy {
-        fn post_file<S: Into<String>, A: AsRef<Path>>(
-            &self,
-            path: S,
-            filepath: A,
-        ) -> Result<Response> {
-            *self.post_file_req.borrow_mut() = (path.into(), to_string(filepath));
-            Ok(Response(None))
-        }
+    const PKG_LIST: &str = r#"{"results":[
+        {"pid":"my_packages:site:1.0","path":"/etc/packages/my_packages/site-1.0.zip",
+         "group":"my_packages","name":"site","version":"1.0","buildCount":1,
+         "lastUnpacked":1600000000000},
+        {"pid":"adobe/aem:tools:","path":"/etc/packages/adobe/aem/tools.zip",
+         "group":"adobe/aem","name":"tools","version":""},
+        {"pid":"my_packages:renamed:2.0","path":"/etc/packages/my_packages/uploaded-by-hand.zip",
+         "group":"my_packages","name":"renamed","version":"2.0"}
+    ],"total":3}"#;
 
-        fn post<S: Into<String>>(&self, path: S) -> Result<Response> {
-            *self.post_req.borrow_mut() = path.into();
-            Ok(Response(None))
-        }
+    #[test]
+    fn test_list_pkgs() -> Result<()> {
+        // given
+        let spy = ClientSpy::with_responses(&[(200, PKG_LIST)]);
+
+        // when
+        let pkgs = list_pkgs(&spy)?;
+
+        // then
+        assert_eq!(spy.get_req(), "/crx/packmgr/list.jsp");
+        assert_eq!(pkgs.len(), 3);
+        assert_eq!(pkgs[0].pid(), "my_packages:site:1.0");
+        assert!(pkgs[0].is_installed());
+        assert_eq!(pkgs[1].rel_path(), "adobe/aem/tools.zip");
+        assert!(!pkgs[1].is_installed());
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_pkg() -> Result<()> {
+        let test_cases = [
+            ("my_packages:site:1.0", "my_packages/site-1.0.zip"),
+            ("my_packages/site-1.0.zip", "my_packages/site-1.0.zip"),
+            ("/etc/packages/adobe/aem/tools.zip", "adobe/aem/tools.zip"),
+            (
+                "my_packages:renamed:2.0",
+                "my_packages/uploaded-by-hand.zip",
+            ),
+        ];
+
+        for (reference, expected) in test_cases {
+            // given
+            let spy = ClientSpy::with_responses(&[(200, PKG_LIST)]);
 
-        fn get<S: Into<String>>(&self, path: S) -> Result<Response> {
-            *self.get_req.borrow_mut() = path.into();
-            Ok(Response(None))
+            // when
+            let info = find_pkg(&spy, reference)?;
+
+            // then
+            assert_eq!(info.rel_path(), expected);
         }
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_pkg_when_missing() {
+        // given
+        let spy = ClientSpy::with_responses(&[(200, PKG_LIST)]);
+
+        // when
+        let res = find_pkg(&spy, "my_packages:site:2.0");
+
+        // then
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "package my_packages:site:2.0 not found on the instance"
+        );
+    }
+
+    #[test]
+    fn test_upload_file_with_force() -> Result<()> {
+        // given
+        let spy = ClientSpy::with_responses(&[(
+            200,
+            r#"{"success":true,"msg":"Package uploaded","path":"/etc/packages/my_packages/site-1.0.zip"}"#,
+        )]);
+
+        // when
+        let path = upload_file(&spy, "site-1.0.zip", true)?;
+
+        // then
+        assert_eq!(path, "/etc/packages/my_packages/site-1.0.zip");
+        assert_eq!(
+            spy.post_file_req(),
+            (
+                "/crx/packmgr/service/.json?cmd=upload&force=true".into(),
+                "site-1.0.zip".into()
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_uninstall_pkg() -> Result<()> {
+        // given
+        let spy = ClientSpy::with_responses(&[(200, SUCCESS)]);
+        let pkg = Pkg::new("my_packages", "site", "1.0");
+
+        // when
+        uninstall_pkg(&spy, &pkg.path())?;
+
+        // then
+        assert_eq!(
+            spy.post_req(),
+            "/crx/packmgr/service/.json/etc/packages/my_packages/site-1.0.zip?cmd=uninstall"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_wait_for_build_when_unauthorized() {
+        // given
+        let spy = ClientSpy::with_responses(&[(401, "Unauthorized")]);
+        let pkg = Pkg::default();
+
+        // when
+        let res = wait_for_build(&spy, &pkg, &BuildWait::default());
+
+        // then
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "failed to check package status"
+        );
+    }
+
+    #[test]
+    fn test_delete_when_deletion_skipped() -> Result<()> {
+        // given
+        let spy = ClientSpy::new();
+        let pkg = Pkg::default();
+
+        // when
+        delete_pkg(&spy, true, &pkg.path())?;
+
+        // then
+        assert_eq!(spy.post_req(), "");
+        Ok(())
+    }
+
+    const SUCCESS: &str = r#"{"success":true,"msg":"Done"}"#;
+
+    fn to_string<A: AsRef<Path>>(path: A) -> String {
+        path.as_ref().display().to_string()
     }
 }
@@ -0,0 +1,278 @@
+//! Mapping between names used by FileVault on the file system (platform names) and names used
+//! in the repository (JCR names), the same as `PlatformNameFormat` from Jackrabbit FileVault.
+use std::fs::read_to_string;
+use std::path::Path;
+
+/// Name of the file which holds properties of the node represented by its parent directory.
+pub(crate) const CONTENT_XML: &str = ".content.xml";
+
+/// Suffix of directories holding additional content of nodes serialized as files.
+const DIR_SUFFIX: &str = ".dir";
+
+const XML_EXT: &str = ".xml";
+
+/// Characters which are not allowed in file names and are escaped as `%xx`.
+const ESCAPED_CHARS: &[char] = &['%', '\\', '/', ':', '<', '>', '"', '|', '?', '*'];
+
+/// Translates platform name (e.g. `_jcr_content`) to JCR name (e.g. `jcr:content`).
+pub(crate) fn repository_name(platform_name: &str) -> String {
+    if let Some(escaped) = platform_name.strip_prefix("__") {
+        // name starting with underscore, e.g. `__a_b` -> `_a_b`
+        return unescape(&format!("_{escaped}"));
+    }
+    if let Some(rest) = platform_name.strip_prefix('_') {
+        if let Some(idx) = rest.find('_').filter(|&idx| idx > 0) {
+            return format!("{}:{}", unescape(&rest[..idx]), unescape(&rest[idx + 1..]));
+        }
+    }
+    unescape(platform_name)
+}
+
+/// Translates JCR name (e.g. `jcr:content`) to platform name (e.g. `_jcr_content`).
+pub(crate) fn platform_name(repository_name: &str) -> String {
+    if let Some((prefix, local)) = repository_name.split_once(':') {
+        if !prefix.is_empty() && !prefix.contains('_') {
+            return format!("_{}_{}", escape(prefix), escape(local));
+        }
+    }
+    let escaped = escape(repository_name);
+    if repository_name.starts_with('_') && repository_name[1..].contains('_') {
+        format!("_{escaped}")
+    } else {
+        escaped
+    }
+}
+
+/// Translates path relative to `jcr_root` directory (e.g. `/content/_jcr_content/.content.xml`)
+/// to JCR path of the node it represents (e.g. `/content/jcr:content`). When `is_docview`
+/// is set, the last segment is treated as XML serialization of the node, so its `.xml`
+/// extension is dropped.
+pub(crate) fn repository_path(platform_path: &str, is_docview: bool) -> String {
+    let mut segments: Vec<&str> = platform_path
+        .split(['/', '\\'])
+        .filter(|s| !s.is_empty())
+        .collect();
+    if segments.last() == Some(&CONTENT_XML) {
+        segments.pop();
+    } else if is_docview {
+        if let Some(last) = segments.pop() {
+            segments.push(last.strip_suffix(XML_EXT).unwrap_or(last));
+        }
+    }
+    let names: Vec<String> = segments
+        .into_iter()
+        .map(|s| s.strip_suffix(DIR_SUFFIX).unwrap_or(s))
+        .map(repository_name)
+        .collect();
+    format!("/{}", names.join("/"))
+}
+
+/// Translates JCR path (e.g. `/content/jcr:content`) to path relative to `jcr_root` directory
+/// (e.g. `/content/_jcr_content`).
+pub(crate) fn platform_path(repository_path: &str) -> String {
+    repository_path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(|s| format!("/{}", platform_name(s)))
+        .collect()
+}
+
+/// Checks if the file is an XML serialization of a node other than `.content.xml`,
+/// e.g. `_cq_dialog.xml`.
+pub(crate) fn is_docview<A: AsRef<Path>>(path: A) -> bool {
+    let path = path.as_ref();
+    let is_xml = path
+        .file_name()
+        .map(|n| n.to_string_lossy())
+        .is_some_and(|n| n.ends_with(XML_EXT) && n != CONTENT_XML);
+    is_xml
+        && read_to_string(path)
+            .map(|content| content.contains("<jcr:root"))
+            .unwrap_or(false)
+}
+
+fn escape(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if ESCAPED_CHARS.contains(&c) {
+                format!("%{:02x}", c as u32)
+            } else {
+                c.to_string()
+            }
+        })
+        .collect()
+}
+
+fn unescape(name: &str) -> String {
+    let mut result = String::with_capacity(name.len());
+    let mut rest = name;
+    while let Some(idx) = rest.find('%') {
+        result.push_str(&rest[..idx]);
+        let decoded = rest
+            .get(idx + 1..idx + 3)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match decoded {
+            Some(byte) => {
+                result.push(char::from(byte));
+                rest = &rest[idx + 3..];
+            }
+            None => {
+                result.push('%');
+                rest = &rest[idx + 1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anyhow::Result;
+    use std::fs::File;
+    use std::io::prelude::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_repository_name() {
+        let test_cases = vec![
+            ("_jcr_content", "jcr:content"),
+            ("_rep_policy", "rep:policy"),
+            ("_cq_dialog", "cq:dialog"),
+            ("_myco_component", "myco:component"),
+            ("_jcr_my_prop", "jcr:my_prop"),
+            ("__a_b", "_a_b"),
+            ("_private", "_private"),
+            ("plain_name", "plain_name"),
+            ("sitemap.xml.txt", "sitemap.xml.txt"),
+            ("a%3ab", "a:b"),
+            ("what%3f", "what?"),
+            ("100%25", "100%"),
+            ("broken%zz", "broken%zz"),
+            ("end%", "end%"),
+        ];
+
+        for (platform, expected) in test_cases {
+            assert_eq!(repository_name(platform), expected, "name: {platform}");
+        }
+    }
+
+    #[test]
+    fn test_platform_name() {
+        let test_cases = vec![
+            ("jcr:content", "_jcr_content"),
+            ("myco:component", "_myco_component"),
+            ("jcr:my_prop", "_jcr_my_prop"),
+            ("_a_b", "__a_b"),
+            ("_private", "_private"),
+            ("plain_name", "plain_name"),
+            ("my_ns:name", "my_ns%3aname"),
+            (":name", "%3aname"),
+            ("a:b:c", "_a_b%3ac"),
+            ("what?", "what%3f"),
+            ("100%", "100%25"),
+        ];
+
+        for (repository, expected) in test_cases {
+            assert_eq!(platform_name(repository), expected, "name: {repository}");
+            assert_eq!(repository_name(expected), repository, "name: {expected}");
+        }
+    }
+
+    #[test]
+    fn test_platform_path() {
+        let test_cases = vec![
+            ("/content/jcr:content", "/content/_jcr_content"),
+            ("/apps/x/cq:dialog/items", "/apps/x/_cq_dialog/items"),
+            ("/content/a?b", "/content/a%3fb"),
+            ("/", ""),
+        ];
+
+        for (repository, expected) in test_cases {
+            assert_eq!(platform_path(repository), expected, "path: {repository}");
+        }
+    }
+
+    #[test]
+    fn test_repository_path() {
+        let test_cases = vec![
+            ("/content/_jcr_content", "/content/jcr:content"),
+            ("/content/_rep_policy", "/content/rep:policy"),
+            ("/content/_oak_root", "/content/oak:root"),
+            ("/content/_sling_order", "/content/sling:order"),
+            ("/content/_granite_var", "/content/granite:var"),
+            ("/content/_cq_dialog", "/content/cq:dialog"),
+            ("/content/_dam_asset", "/content/dam:asset"),
+            ("/content/_exif_fi", "/content/exif:fi"),
+            ("/content/_social_media", "/content/social:media"),
+            ("/apps/_myco_tools", "/apps/myco:tools"),
+            ("/content/_jcr_content/.content.xml", "/content/jcr:content"),
+            ("/content/.content.xml/child", "/content/.content.xml/child"),
+            ("/apps/x/sitemap.xml.txt", "/apps/x/sitemap.xml.txt"),
+            ("/apps/x/config.xml", "/apps/x/config.xml"),
+            ("/apps/x/file.txt.dir/.content.xml", "/apps/x/file.txt"),
+            (
+                "/apps/x/file.txt.dir/_jcr_content",
+                "/apps/x/file.txt/jcr:content",
+            ),
+            ("/content/a%3fb", "/content/a?b"),
+            ("/content/__a_b", "/content/_a_b"),
+            ("", "/"),
+            // Windows
+            ("\\content\\_jcr_content", "/content/jcr:content"),
+            (
+                "\\content\\_jcr_content\\.content.xml",
+                "/content/jcr:content",
+            ),
+        ];
+
+        for (platform, expected) in test_cases {
+            assert_eq!(
+                repository_path(platform, false),
+                expected,
+                "path: {platform}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_repository_path_with_docview() {
+        let test_cases = vec![
+            ("/content/_jcr_content.xml", "/content/jcr:content"),
+            ("/apps/x/_cq_dialog.xml", "/apps/x/cq:dialog"),
+            ("\\apps\\x\\_cq_dialog.xml", "/apps/x/cq:dialog"),
+            ("/apps/x/sitemap.xml.txt", "/apps/x/sitemap.xml.txt"),
+        ];
+
+        for (platform, expected) in test_cases {
+            assert_eq!(
+                repository_path(platform, true),
+                expected,
+                "path: {platform}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_docview() -> Result<()> {
+        // given
+        let tmp_dir = TempDir::new()?;
+        let dialog = tmp_dir.path().join("_cq_dialog.xml");
+        File::create(&dialog)?.write_all(
+            br#"<?xml version="1.0" encoding="UTF-8"?>
+<jcr:root xmlns:jcr="http://www.jcp.org/jcr/1.0" jcr:primaryType="nt:unstructured"/>"#,
+        )?;
+        let config = tmp_dir.path().join("config.xml");
+        File::create(&config)?.write_all(b"<config/>")?;
+        let content_xml = tmp_dir.path().join(CONTENT_XML);
+        File::create(&content_xml)?.write_all(b"<jcr:root/>")?;
+
+        // then
+        assert!(is_docview(&dialog));
+        assert!(!is_docview(&config));
+        assert!(!is_docview(&content_xml));
+        assert!(!is_docview(tmp_dir.path().join("missing.xml")));
+        Ok(())
+    }
+}
@@ -0,0 +1,25 @@
+//! Progress bars of package transfers.
+use indicatif::{ProgressBar, ProgressStyle};
+use std::io::{self, IsTerminal};
+
+/// Progress bar of transferring `len` bytes (or unknown number of them), drawn on stderr
+/// only when it's a terminal, so redirected output and logs stay clean.
+pub(crate) fn transfer(len: Option<u64>, msg: &'static str) -> ProgressBar {
+    if !io::stderr().is_terminal() {
+        return ProgressBar::hidden();
+    }
+    let bar = match len {
+        Some(len) => ProgressBar::new(len).with_style(
+            ProgressStyle::with_template(
+                "{msg} [{bar:30}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
+            )
+            .expect("incorrect progress template")
+            .progress_chars("=> "),
+        ),
+        None => ProgressBar::new_spinner().with_style(
+            ProgressStyle::with_template("{spinner} {msg} {bytes} ({bytes_per_sec})")
+                .expect("incorrect progress template"),
+        ),
+    };
+    bar.with_message(msg)
+}
@@ -0,0 +1,216 @@
+//! Packages with remote content saved by `put` before it installs local files, installed back
+//! by `je rollback`.
+use crate::backup;
+use crate::cfg::Instance;
+use crate::pkgdir::Pkg;
+use anyhow::{Context, Result};
+use getset::Getters;
+use log::{debug, info};
+use serde_derive::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory in the project root with packages of remote content downloaded by `put --snapshot`,
+/// one timestamped subdirectory per package with its metadata; `rollback` looks for them by the
+/// path and profile.
+pub(crate) const SNAPSHOTS_DIR: &str = ".je-snapshots";
+
+const META_FILE: &str = "snapshot.json";
+
+const PKG_FILE: &str = "pkg.zip";
+
+/// Metadata of the package saved in the snapshot directory.
+#[derive(Debug, Getters, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[getset(get = "pub")]
+pub(crate) struct Snapshot {
+    /// name of the profile the package was downloaded from
+    profile: String,
+
+    /// address of the instance, only for information
+    addr: String,
+
+    /// root of the package filter
+    jcr_path: String,
+
+    /// package as it's named on the instance after uploading it back
+    pkg: Pkg,
+
+    #[serde(skip)]
+    dir: PathBuf,
+}
+
+impl Snapshot {
+    pub(crate) fn new<S: Into<String>>(instance: &Instance, jcr_path: S, pkg: Pkg) -> Self {
+        Self {
+            profile: instance.name().clone(),
+            addr: instance.addr().clone(),
+            jcr_path: jcr_path.into(),
+            pkg,
+            dir: PathBuf::new(),
+        }
+    }
+
+    /// Downloaded package, ready to be uploaded back.
+    pub(crate) fn pkg_zip(&self) -> PathBuf {
+        self.dir.join(PKG_FILE)
+    }
+
+    pub(crate) fn remove(self) -> Result<()> {
+        debug!("removing snapshot {}", self.dir.display());
+        fs::remove_dir_all(&self.dir)?;
+        Ok(())
+    }
+
+    fn is_of(&self, profile: &str, jcr_path: &str) -> bool {
+        self.profile == profile && self.jcr_path == jcr_path
+    }
+}
+
+/// Saves downloaded `pkg_zip` with the metadata as a new snapshot in `snapshots_dir`
+/// and removes the oldest snapshots of the same path and profile, so at most `keep`
+/// of them are left (at least the new one is always kept).
+pub(crate) fn save(
+    snapshots_dir: &Path,
+    snapshot: &Snapshot,
+    pkg_zip: &Path,
+    keep: usize,
+) -> Result<PathBuf> {
+    let snapshot_dir = snapshots_dir.join(backup::snapshot_name()?);
+    info!("saving snapshot to {}", snapshot_dir.display());
+    fs::create_dir_all(&snapshot_dir)?;
+    fs::copy(pkg_zip, snapshot_dir.join(PKG_FILE))
+        .context(format!("failed to save package {}", pkg_zip.display()))?;
+    fs::write(
+        snapshot_dir.join(META_FILE),
+        serde_json::to_string_pretty(snapshot)?,
+    )?;
+    let saved = snapshots_of(snapshots_dir, snapshot.profile(), snapshot.jcr_path())?;
+    let excess = saved.len().saturating_sub(keep.max(1));
+    for old in saved.into_iter().take(excess) {
+        old.remove()?;
+    }
+    Ok(snapshot_dir)
+}
+
+/// The most recent snapshot of `jcr_path` downloaded from the `profile` instance.
+pub(crate) fn latest(
+    snapshots_dir: &Path,
+    profile: &str,
+    jcr_path: &str,
+) -> Result<Option<Snapshot>> {
+    Ok(snapshots_of(snapshots_dir, profile, jcr_path)?.pop())
+}
+
+/// Snapshots of the path and profile, from the oldest to the most recent one.
+fn snapshots_of(snapshots_dir: &Path, profile: &str, jcr_path: &str) -> Result<Vec<Snapshot>> {
+    let mut snapshots = Vec::new();
+    for dir in backup::snapshot_dirs(snapshots_dir, META_FILE)? {
+        let meta = fs::read_to_string(dir.join(META_FILE))?;
+        let mut snapshot: Snapshot =
+            serde_json::from_str(&meta).context(format!("broken snapshot {}", dir.display()))?;
+        if snapshot.is_of(profile, jcr_path) {
+            snapshot.dir = dir;
+            snapshots.push(snapshot);
+        }
+    }
+    Ok(snapshots)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::{read_to_string, write};
+    use tempfile::TempDir;
+
+    fn instance(name: &str) -> Instance {
+        Instance::new(name, "http://localhost:4502", "admin", "admin")
+    }
+
+    #[test]
+    fn test_save_and_latest() -> Result<()> {
+        // given
+        let tmp_dir = TempDir::new()?;
+        let snapshots_dir = tmp_dir.path().join(SNAPSHOTS_DIR);
+        let pkg_zip = tmp_dir.path().join("res.zip");
+        for (profile, jcr_path, content) in [
+            ("author", "/apps/site", "first"),
+            ("author", "/apps/site", "second"),
+            ("publish", "/apps/site", "publish"),
+            ("author", "/apps/other", "other"),
+        ] {
+            write(&pkg_zip, content)?;
+            let snapshot = Snapshot::new(&instance(profile), jcr_path, Pkg::default());
+            save(&snapshots_dir, &snapshot, &pkg_zip, 10)?;
+        }
+
+        // when
+        let latest = latest(&snapshots_dir, "author", "/apps/site")?.unwrap();
+
+        // then
+        assert_eq!(latest.profile(), "author");
+        assert_eq!(latest.jcr_path(), "/apps/site");
+        assert_eq!(read_to_string(latest.pkg_zip())?, "second");
+        Ok(())
+    }
+
+    #[test]
+    fn test_latest_when_missing() -> Result<()> {
+        // given
+        let tmp_dir = TempDir::new()?;
+        let snapshots_dir = tmp_dir.path().join(SNAPSHOTS_DIR);
+
+        // when
+        let latest = latest(&snapshots_dir, "author", "/apps/site")?;
+
+        // then
+        assert_eq!(latest, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_keeps_most_recent_of_path() -> Result<()> {
+        // given
+        let tmp_dir = TempDir::new()?;
+        let snapshots_dir = tmp_dir.path().join(SNAPSHOTS_DIR);
+        let pkg_zip = tmp_dir.path().join("res.zip");
+        let other = Snapshot::new(&instance("author"), "/apps/other", Pkg::default());
+        write(&pkg_zip, "other")?;
+        save(&snapshots_dir, &other, &pkg_zip, 1)?;
+
+        // when
+        for content in ["first", "second", "third"] {
+            write(&pkg_zip, content)?;
+            let snapshot = Snapshot::new(&instance("author"), "/apps/site", Pkg::default());
+            save(&snapshots_dir, &snapshot, &pkg_zip, 2)?;
+        }
+
+        // then
+        let site = snapshots_of(&snapshots_dir, "author", "/apps/site")?;
+        assert_eq!(site.len(), 2);
+        assert_eq!(read_to_string(site[0].pkg_zip())?, "second");
+        assert_eq!(read_to_string(site[1].pkg_zip())?, "third");
+        assert!(latest(&snapshots_dir, "author", "/apps/other")?.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_keeps_new_snapshot_when_keep_is_zero() -> Result<()> {
+        // given
+        let tmp_dir = TempDir::new()?;
+        let snapshots_dir = tmp_dir.path().join(SNAPSHOTS_DIR);
+        let pkg_zip = tmp_dir.path().join("res.zip");
+
+        // when
+        for content in ["first", "second"] {
+            write(&pkg_zip, content)?;
+            let snapshot = Snapshot::new(&instance("author"), "/apps/site", Pkg::default());
+            save(&snapshots_dir, &snapshot, &pkg_zip, 0)?;
+        }
+
+        // then
+        let site = snapshots_of(&snapshots_dir, "author", "/apps/site")?;
+        assert_eq!(site.len(), 1);
+        assert_eq!(read_to_string(site[0].pkg_zip())?, "second");
+        Ok(())
+    }
+}
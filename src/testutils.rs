@@ -1,8 +1,11 @@
+use crate::http::{Client, Response};
 use anyhow::Result;
+use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::env;
 use std::fs::{read_to_string, File};
 use std::io::prelude::*;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tempfile::TempDir;
 
 pub(crate) struct TestConfig {
@@ -36,3 +39,83 @@ impl Drop for TestConfig {
         env::set_current_dir(self.initial_dir.clone()).expect("failed to change to an initial dir");
     }
 }
+
+/// Client returning canned responses and remembering the last request of each method.
+pub(crate) struct ClientSpy {
+    post_file_req: RefCell<(String, String)>,
+    post_req: RefCell<String>,
+    get_req: RefCell<String>,
+    responses: RefCell<VecDeque<(u16, String)>>,
+}
+
+impl ClientSpy {
+    pub(crate) fn new() -> Self {
+        Self::with_responses(&[])
+    }
+
+    /// Responses (status code and body) are returned in order by subsequent requests,
+    /// regardless of the method. When all of them are used, empty responses are returned.
+    pub(crate) fn with_responses(responses: &[(u16, &str)]) -> Self {
+        Self {
+            post_file_req: RefCell::new((String::new(), String::new())),
+            post_req: RefCell::new(String::new()),
+            get_req: RefCell::new(String::new()),
+            responses: RefCell::new(
+                responses
+                    .iter()
+                    .map(|(status, body)| (*status, body.to_string()))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn next_response(&self) -> Response {
+        match self.responses.borrow_mut().pop_front() {
+            Some((status, body)) => Response(Some(
+                ::http::Response::builder()
+                    .status(status)
+                    .body(body)
+                    .expect("failed to create canned response")
+                    .into(),
+            )),
+            None => Response(None),
+        }
+    }
+
+    pub(crate) fn remaining_responses(&self) -> usize {
+        self.responses.borrow().len()
+    }
+
+    pub(crate) fn post_file_req(&self) -> (String, String) {
+        self.post_file_req.clone().take()
+    }
+
+    pub(crate) fn post_req(&self) -> String {
+        self.post_req.clone().take()
+    }
+
+    pub(crate) fn get_req(&self) -> String {
+        self.get_req.clone().take()
+    }
+}
+
+impl Client for ClientSpy {
+    fn post_file<S: Into<String>, A: AsRef<Path>>(&self, path: S, filepath: A) -> Result<Response> {
+        *self.post_file_req.borrow_mut() = (path.into(), to_string(filepath));
+        Ok(self.next_response())
+    }
+
+    fn post<S: Into<String>>(&self, path: S) -> Result<Response> {
+        *self.post_req.borrow_mut() = path.into();
+        Ok(self.next_response())
+    }
+
+    fn get<S: Into<String>>(&self, path: S) -> Result<Response> {
+        *self.get_req.borrow_mut() = path.into();
+        Ok(self.next_response())
+    }
+}
+
+fn to_string<A: AsRef<Path>>(path: A) -> String {
+    path.as_ref().display().to_string()
+}
@@ -0,0 +1,185 @@
+use crate::path::Path;
+use log::debug;
+use notify::{Event, EventKind};
+use std::collections::BTreeSet;
+use std::path::{Path as OsPath, PathBuf};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+/// File name suffixes of temporary and swap files created by editors.
+const TEMP_SUFFIXES: &[&str] = &[
+    "~",
+    ".swp",
+    ".swo",
+    ".swx",
+    ".tmp",
+    ".bak",
+    "___jb_tmp___",
+    "___jb_old___",
+];
+
+/// File names of temporary files created by editors and operating systems.
+const TEMP_NAMES: &[&str] = &["4913", ".DS_Store", "Thumbs.db"];
+
+/// Blocks until at least one change is reported, then collects further changes until
+/// there is no new event for `debounce`. Returns `None` when the watcher is gone.
+pub(crate) fn next_changes(
+    rx: &Receiver<notify::Result<Event>>,
+    debounce: Duration,
+) -> Option<Vec<PathBuf>> {
+    let mut changed = Vec::new();
+    collect(rx.recv().ok()?, &mut changed);
+    loop {
+        match rx.recv_timeout(debounce) {
+            Ok(event) => collect(event, &mut changed),
+            Err(RecvTimeoutError::Timeout) => return Some(changed),
+            Err(RecvTimeoutError::Disconnected) => return None,
+        }
+    }
+}
+
+fn collect(event: notify::Result<Event>, changed: &mut Vec<PathBuf>) {
+    match event {
+        Ok(event) if !matches!(event.kind, EventKind::Access(_)) => {
+            debug!("file event: {:?}", event);
+            changed.extend(event.paths);
+        }
+        Ok(event) => debug!("skipping access event: {:?}", event),
+        Err(e) => debug!("watcher error: {}", e),
+    }
+}
+
+/// Turns changed paths into paths which should be uploaded: temporary files and paths
+/// which do not exist anymore are skipped and paths covered by other changed directory
+/// are removed.
+pub(crate) fn paths_to_sync(changed: Vec<PathBuf>) -> Vec<Path> {
+    let candidates: BTreeSet<PathBuf> = changed
+        .into_iter()
+        .filter(|p| !is_temp_file(p))
+        .filter(|p| {
+            let exists = p.exists();
+            if !exists {
+                debug!("{} does not exist anymore, skipping", p.display());
+            }
+            exists
+        })
+        .collect();
+    candidates
+        .iter()
+        .filter(|p| !p.ancestors().skip(1).any(|a| candidates.contains(a)))
+        .map(|p| Path::new(p.display().to_string()))
+        .collect()
+}
+
+fn is_temp_file<A: AsRef<OsPath>>(path: A) -> bool {
+    let name = match path.as_ref().file_name() {
+        Some(name) => name.to_string_lossy(),
+        None => return false,
+    };
+    TEMP_NAMES.contains(&name.as_ref())
+        || TEMP_SUFFIXES.iter().any(|s| name.ends_with(s))
+        || name.starts_with(".#")
+        || (name.starts_with('#') && name.ends_with('#'))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anyhow::Result;
+    use notify::event::{AccessKind, CreateKind, ModifyKind};
+    use std::fs::{create_dir_all, File};
+    use std::sync::mpsc::channel;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_is_temp_file() {
+        let test_cases = vec![
+            ("/jcr_root/content/.content.xml", false),
+            ("/jcr_root/content/component.html", false),
+            ("/jcr_root/content/.component.html.swp", true),
+            ("/jcr_root/content/component.html~", true),
+            ("/jcr_root/content/component.html___jb_tmp___", true),
+            ("/jcr_root/content/component.html___jb_old___", true),
+            ("/jcr_root/content/.#component.html", true),
+            ("/jcr_root/content/#component.html#", true),
+            ("/jcr_root/content/4913", true),
+            ("/jcr_root/content/.DS_Store", true),
+        ];
+
+        for (path, expected) in test_cases {
+            assert_eq!(is_temp_file(path), expected, "path: {path}");
+        }
+    }
+
+    #[test]
+    fn test_paths_to_sync() -> Result<()> {
+        // given
+        let tmp_dir = TempDir::new()?;
+        let root = tmp_dir.path().join("jcr_root/apps/project");
+        create_dir_all(root.join("components/new"))?;
+        File::create(root.join("components/new/new.html"))?;
+        File::create(root.join("components/new/.content.xml"))?;
+        File::create(root.join("page.html"))?;
+        File::create(root.join(".page.html.swp"))?;
+        let changed = vec![
+            root.join("page.html"),
+            root.join(".page.html.swp"),
+            root.join("components/new/new.html"),
+            root.join("components/new"),
+            root.join("components/new/.content.xml"),
+            root.join("page.html"),
+            root.join("removed.html"),
+        ];
+
+        // when
+        let paths = paths_to_sync(changed);
+
+        // then
+        let paths: Vec<String> = paths.iter().map(Path::full).collect();
+        assert_eq!(
+            paths,
+            vec![
+                root.join("components/new").display().to_string(),
+                root.join("page.html").display().to_string(),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_next_changes_collects_burst_of_events() {
+        // given
+        let (tx, rx) = channel();
+        tx.send(Ok(
+            Event::new(EventKind::Create(CreateKind::File)).add_path("/a".into())
+        ))
+        .unwrap();
+        tx.send(Ok(
+            Event::new(EventKind::Access(AccessKind::Any)).add_path("/b".into())
+        ))
+        .unwrap();
+        tx.send(Ok(
+            Event::new(EventKind::Modify(ModifyKind::Any)).add_path("/c".into())
+        ))
+        .unwrap();
+
+        // when
+        let changes = next_changes(&rx, Duration::from_millis(10));
+
+        // then
+        assert_eq!(changes, Some(vec!["/a".into(), "/c".into()]));
+    }
+
+    #[test]
+    fn test_next_changes_when_watcher_is_gone() {
+        // given
+        let (tx, rx) = channel::<notify::Result<Event>>();
+        drop(tx);
+
+        // when
+        let changes = next_changes(&rx, Duration::from_millis(10));
+
+        // then
+        assert_eq!(changes, None);
+    }
+}